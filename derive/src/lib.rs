@@ -0,0 +1,175 @@
+//! `#[derive(GpuBuffer)]` for `alkomp`, enabled with its `derive` feature
+//!
+//! See `alkomp::GpuBuffer` for the generated items.
+
+extern crate proc_macro;
+
+use proc_macro::TokenStream;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Fields, GenericArgument, PathArguments, Type};
+
+#[proc_macro_derive(GpuBuffer, attributes(gpu))]
+pub fn derive_gpu_buffer(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    match expand(&input) {
+        Ok(tokens) => tokens.into(),
+        Err(error) => error.to_compile_error().into(),
+    }
+}
+
+/// A field of the struct bound as one storage buffer
+struct BufferField {
+    ident: syn::Ident,
+    element: Type,
+    binding: u32,
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<proc_macro2::TokenStream> {
+    if !input.generics.params.is_empty() {
+        return Err(syn::Error::new_spanned(
+            &input.generics,
+            "GpuBuffer can't be derived for generic structs",
+        ));
+    }
+    let fields = match &input.data {
+        Data::Struct(data) => match &data.fields {
+            Fields::Named(fields) => &fields.named,
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &input.ident,
+                    "GpuBuffer needs a struct with named fields",
+                ))
+            }
+        },
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &input.ident,
+                "GpuBuffer can only be derived for structs",
+            ))
+        }
+    };
+
+    let mut buffers = vec![];
+    for (index, field) in fields.iter().enumerate() {
+        buffers.push(BufferField {
+            ident: field.ident.clone().unwrap(),
+            element: vec_element(&field.ty)?,
+            binding: binding_attr(&field.attrs)?.unwrap_or(index as u32),
+        });
+    }
+    for (index, buffer) in buffers.iter().enumerate() {
+        if let Some(earlier) = buffers[..index]
+            .iter()
+            .find(|earlier| earlier.binding == buffer.binding)
+        {
+            return Err(syn::Error::new_spanned(
+                &buffer.ident,
+                format!(
+                    "binding {} is already used by `{}`",
+                    buffer.binding, earlier.ident
+                ),
+            ));
+        }
+    }
+
+    let vis = &input.vis;
+    let name = &input.ident;
+    let gpu_name = format_ident!("{}Gpu", name);
+    let idents: Vec<_> = buffers.iter().map(|b| &b.ident).collect();
+    let elements: Vec<_> = buffers.iter().map(|b| &b.element).collect();
+    let bindings: Vec<_> = buffers.iter().map(|b| b.binding).collect();
+    let doc = format!(
+        "The buffers of a `{}` on the device, see `alkomp::GpuBuffer`",
+        name
+    );
+
+    Ok(quote! {
+        #[doc = #doc]
+        #vis struct #gpu_name {
+            #( pub #idents: ::alkomp::GPUData<[#elements]>, )*
+        }
+
+        const _: fn() = || {
+            #( ::alkomp::assert_pod::<#elements>(); )*
+        };
+
+        impl ::alkomp::GpuBuffer for #name {
+            type Gpu = #gpu_name;
+
+            fn to_device(&self, device: &mut ::alkomp::Device) -> #gpu_name {
+                #gpu_name {
+                    #( #idents: device.to_device(self.#idents.as_slice()), )*
+                }
+            }
+
+            fn params(gpu: &#gpu_name) -> ::alkomp::ParamsBuilder<'_> {
+                ::alkomp::ParamsBuilder::new()
+                    #( .param_at(#bindings, Some(&gpu.#idents)) )*
+            }
+
+            #[cfg(not(target_arch = "wasm32"))]
+            fn get(
+                device: &mut ::alkomp::Device,
+                gpu: &#gpu_name,
+            ) -> Result<Self, ::alkomp::GetError> {
+                Ok(#name {
+                    #( #idents: device.get_blocking(&gpu.#idents)?.into_vec(), )*
+                })
+            }
+        }
+    })
+}
+
+/// The element type `T` of a `Vec<T>` field
+fn vec_element(ty: &Type) -> syn::Result<Type> {
+    if let Type::Path(path) = ty {
+        if let Some(segment) = path.path.segments.last() {
+            if segment.ident == "Vec" {
+                if let PathArguments::AngleBracketed(args) = &segment.arguments {
+                    if let Some(GenericArgument::Type(element)) = args.args.first() {
+                        return Ok(element.clone());
+                    }
+                }
+            }
+        }
+    }
+    Err(syn::Error::new_spanned(
+        ty,
+        "GpuBuffer fields must be `Vec<T>` with `T: bytemuck::Pod`",
+    ))
+}
+
+/// The binding of `#[gpu(binding = N)]`, if the field has one
+fn binding_attr(attrs: &[syn::Attribute]) -> syn::Result<Option<u32>> {
+    let mut binding = None;
+    for attr in attrs.iter().filter(|attr| attr.path.is_ident("gpu")) {
+        let list = match attr.parse_meta()? {
+            syn::Meta::List(list) => list,
+            meta => {
+                return Err(syn::Error::new_spanned(
+                    meta,
+                    "expected #[gpu(binding = N)]",
+                ))
+            }
+        };
+        for nested in list.nested.iter() {
+            match nested {
+                syn::NestedMeta::Meta(syn::Meta::NameValue(pair))
+                    if pair.path.is_ident("binding") =>
+                {
+                    match &pair.lit {
+                        syn::Lit::Int(lit) => binding = Some(lit.base10_parse()?),
+                        lit => return Err(syn::Error::new_spanned(lit, "expected an integer")),
+                    }
+                }
+                _ => {
+                    return Err(syn::Error::new_spanned(
+                        nested,
+                        "unknown gpu attribute, expected #[gpu(binding = N)]",
+                    ))
+                }
+            }
+        }
+    }
+    Ok(binding)
+}
@@ -0,0 +1,53 @@
+//! Compares the latency of `run_fused` against separate `to_device`, `call` and `get`
+//!
+//! cargo run --release --example fused --features glsl
+
+use std::time::Instant;
+
+const RUNS: u32 = 100;
+
+fn main() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Data {
+        float[] data;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        data[index] = data[index] * 2.0;
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let input: Vec<f32> = (0..1024).map(|i| i as f32).collect();
+    let workspace = (input.len() as u32, 1, 1);
+
+    let mut device = alkomp::Device::new(0).unwrap();
+
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        let data_gpu = device.to_device(input.as_slice());
+        let args = alkomp::ParamsBuilder::new()
+            .param(Some(&data_gpu))
+            .build(Some(0));
+        let compute = device.compile("main", &shader, &args.0).unwrap();
+        device.call(&compute, workspace, &args.1).unwrap();
+        futures::executor::block_on(device.get(&data_gpu)).unwrap();
+    }
+    let separate = start.elapsed() / RUNS;
+
+    let start = Instant::now();
+    for _ in 0..RUNS {
+        device
+            .run_fused(&shader, "main", input.as_slice(), workspace)
+            .unwrap();
+    }
+    let fused = start.elapsed() / RUNS;
+
+    println!("separate submissions: {:?} per run", separate);
+    println!("fused submission:     {:?} per run", fused);
+}
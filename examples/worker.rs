@@ -0,0 +1,49 @@
+//! Submits jobs to a device owned by a worker thread from several threads at once
+//!
+//! cargo run --example worker --features glsl
+
+fn main() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Data {
+        uint[] data;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        data[index] = data[index] * data[index];
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let worker = alkomp::Device::new(0).unwrap().spawn_worker();
+
+    let threads: Vec<_> = (0..4u32)
+        .map(|t| {
+            let worker = worker.clone();
+            let shader = shader.clone();
+            std::thread::spawn(move || {
+                let input: Vec<u32> = (0..8).map(|i| i + 8 * t).collect();
+                let len = input.len();
+                let result = worker.run(move |device| {
+                    let data_gpu = device.to_device(input.as_slice());
+                    let args = alkomp::ParamsBuilder::new()
+                        .param(Some(&data_gpu))
+                        .build(Some(0));
+                    let compute = device.compile("main", &shader, &args.0).unwrap();
+                    device.call(&compute, (len as u32, 1, 1), &args.1).unwrap();
+                    futures::executor::block_on(device.get(&data_gpu)).unwrap()
+                });
+                let output = futures::executor::block_on(result).unwrap();
+                println!("thread {}: {:?}", t, &output[0..len]);
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}
@@ -19,7 +19,7 @@ impl Device {
     #[new]
     fn new(idx: usize) -> Self {
         Device {
-            device: alkomp::Device::new(idx),
+            device: alkomp::Device::new(idx).unwrap(),
         }
     }
 
@@ -80,7 +80,7 @@ impl Device {
             .compile(entry.as_str(), &shader, &args.0)
             .unwrap();
 
-        self.device.call(compute, workspace, &args.1);
+        self.device.call(&compute, workspace, &args.1).unwrap();
     }
 
     fn get<'py>(
@@ -97,7 +97,7 @@ impl Device {
         //TODO: this needs to copy data in order to reshape?
         let data: &PyArrayDyn<DataType> = match dtype {
             DataType::Float32 => {
-                let d: Vec<f32> = data[..data.len() - 1]
+                let d: Vec<f32> = data
                     .chunks_exact(std::mem::size_of::<f32>())
                     .map(|b| bytemuck::from_bytes::<f32>(b).clone())
                     .collect();
@@ -109,7 +109,7 @@ impl Device {
                 x
             }
             DataType::Uint32 => {
-                let d: Vec<u32> = data[..data.len() - 1]
+                let d: Vec<u32> = data
                     .chunks_exact(std::mem::size_of::<u32>())
                     .map(|b| bytemuck::from_bytes::<u32>(b).clone())
                     .collect();
@@ -0,0 +1,72 @@
+use crate::device::*;
+use crate::error::*;
+
+///
+/// An output buffer that keeps its contents across dispatches, for streaming
+/// accumulation such as histograms or running sums over batches of input.
+///
+/// Each batch is dispatched with the input bound at `binding = 0` and the accumulator at
+/// `binding = 1` of set 0, so the kernel should be compiled against `Accumulator::layout`.
+/// ```ignore
+///     let mut hist = alkomp::Accumulator::<u32>::new(&mut device, 4);
+///     let layout = alkomp::Accumulator::<u32>::layout::<u32>();
+///     let compute = device.compile("main", &shader, &layout).unwrap();
+///     for batch in &batches {
+///         let input = device.to_device(batch.as_slice());
+///         hist.add_batch(&mut device, &compute, &input, (batch.len() as u32, 1, 1))
+///             .unwrap();
+///     }
+///     let bins = futures::executor::block_on(hist.read(&mut device)).unwrap();
+/// ```
+///
+pub struct Accumulator<T: bytemuck::Pod> {
+    data: GPUData<[T]>,
+}
+
+impl<T: bytemuck::Pod> Accumulator<T> {
+    /// Allocates a zeroed accumulator of `len` elements
+    pub fn new(device: &mut Device, len: usize) -> Self {
+        Accumulator {
+            data: device.alloc_zeroed(len),
+        }
+    }
+
+    /// The layout batches are dispatched with: the input of type `U` at binding 0 and
+    /// the accumulator at binding 1
+    pub fn layout<U: bytemuck::Pod>() -> GPUSetGroupLayout {
+        ParamsBuilder::new()
+            .param::<U>(None)
+            .param::<T>(None)
+            .build(Some(0))
+            .0
+    }
+
+    /// Zeroes the accumulator so a new series of batches can start
+    pub fn reset(&self, device: &mut Device) {
+        device.clear(&self.data);
+    }
+
+    /// Runs `compute` over one batch of `input`, accumulating into the existing contents
+    pub fn add_batch<U: bytemuck::Pod>(
+        &self,
+        device: &mut Device,
+        compute: &GPUCompute,
+        input: &GPUData<[U]>,
+        workspace: (u32, u32, u32),
+    ) -> Result<wgpu::SubmissionIndex, CallError> {
+        let args = ParamsBuilder::new()
+            .param(Some(input))
+            .param(Some(&self.data))
+            .build(Some(0));
+        device.submit_compute(compute, workspace, &args.1, &[])
+    }
+
+    /// Reads the accumulated values back from the device
+    pub async fn read(&self, device: &mut Device) -> Result<Box<[T]>, GetError> {
+        device.get(&self.data).await
+    }
+
+    pub fn data(&self) -> &GPUData<[T]> {
+        &self.data
+    }
+}
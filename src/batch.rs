@@ -0,0 +1,64 @@
+use crate::device::*;
+use crate::error::*;
+use std::collections::HashMap;
+
+///
+/// Dispatches recorded into a single command encoder and submitted together, obtained from
+/// `Device::begin_batch`. Each `add` is its own compute pass, and wgpu orders the passes so
+/// storage writes of one are visible to the next, saving a submission per kernel of a
+/// multi-stage pipeline.
+/// ```ignore
+///     let mut batch = device.begin_batch();
+///     batch.add(&reduce_blocks, (blocks, 1, 1), &args)?;
+///     batch.add(&reduce_partials, (1, 1, 1), &args)?;
+///     batch.submit();
+/// ```
+///
+pub struct CommandBatch<'d> {
+    device: &'d mut Device,
+    encoder: wgpu::CommandEncoder,
+    entries: Vec<String>,
+}
+
+impl Device {
+    /// Starts recording dispatches to submit at once, see `CommandBatch`
+    pub fn begin_batch(&mut self) -> CommandBatch<'_> {
+        let encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        CommandBatch {
+            device: self,
+            encoder,
+            entries: vec![],
+        }
+    }
+}
+
+impl<'d> CommandBatch<'d> {
+    /// Records a dispatch of `gpu_compute` after the ones already in the batch, checked like
+    /// `call`. Nothing runs until `submit`.
+    pub fn add<'a>(
+        &mut self,
+        gpu_compute: &GPUCompute,
+        workspace: (u32, u32, u32),
+        args: &HashMap<u32, wgpu::BindGroupEntry<'a>>,
+    ) -> Result<(), CallError> {
+        self.device.record_compute(
+            &mut self.encoder,
+            gpu_compute,
+            workspace.into(),
+            &single_set(args),
+            &[],
+            &[],
+            &[],
+        )?;
+        self.entries.push(gpu_compute.entry.clone());
+        Ok(())
+    }
+
+    /// Submits every recorded dispatch in one submission
+    pub fn submit(self) -> wgpu::SubmissionIndex {
+        let entry = self.entries.join(", ");
+        self.device.submit_encoder(self.encoder, &entry)
+    }
+}
@@ -0,0 +1,44 @@
+use crate::device::*;
+use crate::error::*;
+
+///
+/// A struct of arrays whose fields are uploaded as separate storage buffers, one binding
+/// each, usually implemented with `#[derive(GpuBuffer)]` from the `derive` feature.
+///
+/// The derive requires named `Vec<T>` fields with `T: bytemuck::Pod` and generates a
+/// `<Name>Gpu` struct holding one `GPUData` per field. Fields are bound in declaration
+/// order, or at the binding given with `#[gpu(binding = N)]`. Two fields can't share a
+/// binding, including an explicit one and another field's position.
+/// ```ignore
+///     #[derive(alkomp::GpuBuffer)]
+///     struct Particles {
+///         pos: Vec<f32>,
+///         #[gpu(binding = 3)]
+///         vel: Vec<f32>,
+///     }
+///
+///     let gpu = particles.to_device(&mut device);
+///     let args = Particles::params(&gpu).build(Some(0));
+///     let compute = device.compile("main", &shader, &args.0).unwrap();
+///     device.call(&compute, (n, 1, 1), &args.1).unwrap();
+///     let particles = Particles::get(&mut device, &gpu).unwrap();
+/// ```
+///
+pub trait GpuBuffer: Sized {
+    /// The buffers of the fields on the device
+    type Gpu;
+
+    /// Uploads every field into its own storage buffer
+    fn to_device(&self, device: &mut Device) -> Self::Gpu;
+
+    /// A `ParamsBuilder` with every buffer of `gpu` at the binding of its field
+    fn params(gpu: &Self::Gpu) -> ParamsBuilder<'_>;
+
+    /// Reads every buffer of `gpu` back into the fields of a new value
+    #[cfg(not(target_arch = "wasm32"))]
+    fn get(device: &mut Device, gpu: &Self::Gpu) -> Result<Self, GetError>;
+}
+
+/// Used by `#[derive(GpuBuffer)]` to reject fields whose elements aren't `Pod`
+#[doc(hidden)]
+pub fn assert_pod<T: bytemuck::Pod>() {}
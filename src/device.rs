@@ -1,311 +1,3065 @@
+use crate::error::*;
 use futures::executor::block_on;
+use futures::FutureExt;
 use std::collections::HashMap;
+#[cfg(not(target_arch = "wasm32"))]
+use std::collections::VecDeque;
 use std::marker::PhantomData;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
 use wgpu::util::DeviceExt;
 
+/// Lists the adapters of the primary backends, each with the index `Device::new` takes
 #[cfg(not(target_arch = "wasm32"))]
 pub fn query() -> Vec<DeviceInfo> {
-    let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
-    let adapter = instance.enumerate_adapters(wgpu::BackendBit::PRIMARY);
+    let instance = create_instance(wgpu::Backends::PRIMARY);
+    query_with_instance(&instance)
+}
+
+/// Same as `query`, but only the adapters of the backends in `backend`, e.g. to skip the
+/// Dx12 entry of a card that is also listed for Vulkan. The adapters keep their relative
+/// order, but their positions differ from the indices `Device::new` takes, see
+/// `DeviceInfo::index`.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn query_by_backend(backend: wgpu::Backends) -> Vec<DeviceInfo> {
+    query()
+        .into_iter()
+        .filter(|device| backend.contains(wgpu::Backends::from(device.info.backend)))
+        .collect()
+}
+
+/// Same as `query`, but enumerates the adapters of an existing `wgpu::Instance`
+#[cfg(not(target_arch = "wasm32"))]
+pub fn query_with_instance(instance: &wgpu::Instance) -> Vec<DeviceInfo> {
+    let adapter = instance.enumerate_adapters(wgpu::Backends::PRIMARY);
 
-    let devices: Vec<DeviceInfo> = adapter.map(|a| DeviceInfo { info: a.get_info() }).collect();
+    let devices: Vec<DeviceInfo> = adapter
+        .iter()
+        .enumerate()
+        .map(|(index, a)| DeviceInfo::from_adapter(a, Some(index)))
+        .collect();
     devices
 }
 
-pub fn load_shader(path: &str) -> Vec<u32> {
-    const MAGIC_NUMBER: u32 = 0x0723_0203;
-    let bytes = std::fs::read(path).unwrap();
+/// Reads a binary SPIR-V module, e.g. produced by `glslangValidator -V`
+pub fn load_shader(path: &str) -> Result<Vec<u32>, ShaderLoadError> {
+    const MAGIC_NUMBER: u32 = 0x0723_0203;
+    let bytes = std::fs::read(path).map_err(ShaderLoadError::Io)?;
+
+    if bytes.len() % 4 != 0 {
+        return Err(ShaderLoadError::UnalignedLength);
+    }
+    let words: Vec<u32> = bytes
+        .chunks_exact(4)
+        .map(|word| u32::from_le_bytes([word[0], word[1], word[2], word[3]]))
+        .collect();
+    match words.first() {
+        Some(&MAGIC_NUMBER) => Ok(words),
+        found => Err(ShaderLoadError::NotSpirv {
+            found_magic: found.copied().unwrap_or(0),
+        }),
+    }
+}
+
+/// Offsets of mapped ranges must be a multiple of this
+const MAP_ALIGNMENT: u64 = 8;
+
+/// Usage of the storage buffer of a `GPUData`
+const STORAGE_USAGE: wgpu::BufferUsages = wgpu::BufferUsages::from_bits_truncate(
+    wgpu::BufferUsages::STORAGE.bits()
+        | wgpu::BufferUsages::COPY_DST.bits()
+        | wgpu::BufferUsages::COPY_SRC.bits(),
+);
+
+/// Buffers larger than this are read back in several mappings by `get`
+pub const DEFAULT_READBACK_CHUNK_SIZE: u64 = 64 << 20;
+
+/// Bytes of dropped buffers a device keeps for reuse, see `Device::set_pool_budget`
+pub const DEFAULT_POOL_BUDGET: u64 = 64 << 20;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub struct Device {
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    pub info: Option<DeviceInfo>,
+    readback_chunk_size: u64,
+    label: Option<String>,
+    allocated: Arc<AtomicU64>,
+    inflight: Inflight,
+    trace: Option<Trace>,
+    specializations: SpecializationCache,
+    lost: Arc<AtomicBool>,
+    buffer_pool: Arc<Mutex<BufferPool>>,
+    /// The instance the device was created from, if the device created it itself
+    instance: Option<Arc<wgpu::Instance>>,
+    pending_maps: Arc<PendingMaps>,
+    poll_thread: Option<std::thread::JoinHandle<()>>,
+    pub(crate) map_pipelines: HashMap<(String, &'static str), GPUCompute>,
+    pub(crate) ops_pipelines: HashMap<String, GPUCompute>,
+}
+#[cfg(target_arch = "wasm32")]
+pub struct Device {
+    pub device: wgpu::Device,
+    pub queue: wgpu::Queue,
+    readback_chunk_size: u64,
+    label: Option<String>,
+    allocated: Arc<AtomicU64>,
+    specializations: SpecializationCache,
+    lost: Arc<AtomicBool>,
+    buffer_pool: Arc<Mutex<BufferPool>>,
+    pub(crate) map_pipelines: HashMap<(String, &'static str), GPUCompute>,
+    pub(crate) ops_pipelines: HashMap<String, GPUCompute>,
+}
+
+/// Number of maps requested by `Device::readback_async` that haven't completed yet, which the
+/// thread of `Device::spawn_poll_thread` sleeps on while there are none
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct PendingMaps {
+    /// The count and whether the poll thread should exit
+    state: Mutex<(usize, bool)>,
+    changed: std::sync::Condvar,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl PendingMaps {
+    fn add(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.0 += 1;
+            self.changed.notify_all();
+        }
+    }
+
+    fn remove(&self) {
+        if let Ok(mut state) = self.state.lock() {
+            state.0 -= 1;
+        }
+    }
+
+    fn set_stop(&self, stop: bool) {
+        if let Ok(mut state) = self.state.lock() {
+            state.1 = stop;
+            self.changed.notify_all();
+        }
+    }
+
+    /// Blocks until a map is pending, or returns `false` once the poll thread should exit
+    fn wait(&self) -> bool {
+        let state = match self.state.lock() {
+            Ok(state) => state,
+            Err(_) => return false,
+        };
+        match self
+            .changed
+            .wait_while(state, |(count, stop)| *count == 0 && !*stop)
+        {
+            Ok(state) => !state.1,
+            Err(_) => false,
+        }
+    }
+}
+
+/// Data living on the device, created by `Device::to_device`.
+///
+/// A `GPUData` may be dropped while a submitted dispatch still uses it. Dropping only releases
+/// the handle: wgpu keeps the buffers alive until the last submission referencing them has
+/// completed and frees them afterwards, so fast allocate/free loops never touch freed memory.
+/// The staging buffer of data uploaded with `to_device` is then kept by the device for the
+/// next upload of the same size, see `Device::clear_pool`. Storage buffers are never reused,
+/// as bind groups built from the data may still refer to them.
+pub struct GPUData<T: ?Sized> {
+    pub(crate) staging_buffer: Arc<wgpu::Buffer>,
+    pub storage_buffer: wgpu::Buffer,
+    pub size: u64,
+    pub phantom: PhantomData<T>,
+    usage: wgpu::BufferUsages,
+    allocation: Allocation,
+    pool: Option<Arc<Mutex<BufferPool>>>,
+    /// Set while a map of one of the buffers may be pending, see `PendingMap`
+    map_pending: AtomicBool,
+}
+
+/// Bytes of a `GPUData` counted in its device's total, released again on drop
+struct Allocation {
+    bytes: u64,
+    total: Arc<AtomicU64>,
+}
+
+impl Allocation {
+    fn new(total: &Arc<AtomicU64>, bytes: u64) -> Self {
+        total.fetch_add(bytes, Ordering::SeqCst);
+        Allocation {
+            bytes,
+            total: total.clone(),
+        }
+    }
+}
+
+impl Drop for Allocation {
+    fn drop(&mut self) {
+        self.total.fetch_sub(self.bytes, Ordering::SeqCst);
+    }
+}
+
+/// Staging buffers of dropped `GPUData`, by padded size, reused by later uploads. Holds at
+/// most `budget` bytes.
+struct BufferPool {
+    staging: HashMap<u64, Vec<Arc<wgpu::Buffer>>>,
+    bytes: u64,
+    budget: u64,
+}
+
+impl Default for BufferPool {
+    fn default() -> Self {
+        BufferPool {
+            staging: HashMap::new(),
+            bytes: 0,
+            budget: DEFAULT_POOL_BUDGET,
+        }
+    }
+}
+
+impl BufferPool {
+    fn clear(&mut self) {
+        self.staging.clear();
+        self.bytes = 0;
+    }
+}
+
+/// Hands the staging buffer back to the pool of the device if the data came from `to_device`,
+/// nothing else holds the buffer, it isn't mapped and it fits the budget. The upload reusing
+/// it is ordered after any submission still using it.
+impl<T: ?Sized> Drop for GPUData<T> {
+    fn drop(&mut self) {
+        let size = self.padded_size();
+        if Arc::strong_count(&self.staging_buffer) != 1 || *self.map_pending.get_mut() {
+            return;
+        }
+        if let Some(Ok(mut pool)) = self.pool.as_ref().map(|pool| pool.lock()) {
+            if pool.bytes + size > pool.budget {
+                return;
+            }
+            pool.bytes += size;
+            pool.staging
+                .entry(size)
+                .or_insert_with(Vec::new)
+                .push(self.staging_buffer.clone());
+        }
+    }
+}
+
+impl<T: ?Sized> GPUData<T> {
+    /// Size of the buffers on the device, `size` rounded up to a multiple of 4 bytes
+    pub fn padded_size(&self) -> u64 {
+        align_to(self.size, wgpu::COPY_BUFFER_ALIGNMENT)
+    }
+    /// The usage flags the storage buffer was created with
+    pub fn usage(&self) -> wgpu::BufferUsages {
+        self.usage
+    }
+    /// Bytes allocated on the device for this data, both buffers including padding
+    pub fn allocated_bytes(&self) -> u64 {
+        self.allocation.bytes
+    }
+    /// Whether the buffer can be copied back to the host by `get`
+    pub fn is_readable(&self) -> bool {
+        self.usage.contains(wgpu::BufferUsages::COPY_SRC)
+    }
+    /// Whether `get` maps the storage buffer directly, see `Device::to_device_mappable`
+    pub fn is_mappable(&self) -> bool {
+        self.usage.contains(wgpu::BufferUsages::MAP_READ)
+    }
+    /// Whether the buffer can hold the arguments of an indirect dispatch
+    pub fn is_indirect(&self) -> bool {
+        self.usage.contains(wgpu::BufferUsages::INDIRECT)
+    }
+    /// The kind of memory the storage buffer was placed in, where the backend reports it.
+    ///
+    /// wgpu chooses the memory type internally and currently does not expose it on any backend:
+    /// Vulkan and Metal know it in the hal layer but wgpu does not pass it through, DX12 and
+    /// the web never report it. This returns `None` until wgpu provides it.
+    pub fn memory_properties(&self) -> Option<MemoryProperties> {
+        None
+    }
+}
+
+impl<T> GPUData<[T]> {
+    /// Number of elements of type `T` in the buffer
+    pub fn len(&self) -> usize {
+        self.size as usize / std::mem::size_of::<T>()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// Memory flags of a buffer, see `GPUData::memory_properties`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryProperties {
+    pub device_local: bool,
+    pub host_visible: bool,
+    pub host_coherent: bool,
+}
+
+impl Device {
+    /// Creates a device on the adapter at `device_index` of `query`, failing instead of
+    /// panicking so callers can fall back to another adapter
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new(device_index: usize) -> Result<Self, DeviceError> {
+        let instance = create_instance(wgpu::Backends::PRIMARY);
+        Self::from_instance(&instance, device_index).map(|device| device.owning(instance))
+    }
+
+    /// Like `new`, but requests `features` and `limits` instead of the defaults, e.g. a larger
+    /// `max_storage_buffer_binding_size` for big buffers. `MAPPABLE_PRIMARY_BUFFERS` is always
+    /// added, as the staging buffers of `GPUData` depend on it. Fails with
+    /// `DeviceError::MissingFeatures` when the adapter lacks a feature, e.g.
+    /// `Features::SHADER_F64` for `double` buffers, and with
+    /// `DeviceError::RequestDeviceFailed` when it can't provide the limits.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new_with(
+        device_index: usize,
+        features: wgpu::Features,
+        limits: wgpu::Limits,
+    ) -> Result<Self, DeviceError> {
+        let instance = create_instance(wgpu::Backends::PRIMARY);
+        let adapter = select_adapter(&instance, device_index)?;
+        let missing = features - adapter.features();
+        if !missing.is_empty() {
+            return Err(DeviceError::MissingFeatures(missing));
+        }
+        Self::request_device(
+            &adapter,
+            Some(device_index),
+            features | wgpu::Features::MAPPABLE_PRIMARY_BUFFERS,
+            limits,
+        )
+        .map(|device| device.owning(instance))
+    }
+
+    /// Creates a device from an existing `wgpu::Instance`, e.g. one shared with a renderer
+    /// or created with validation layers enabled. `device_index` indexes the adapters
+    /// returned by `query_with_instance` for the same instance. The instance stays with the
+    /// caller, so `spawn_poll_thread` can't drive such a device.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn from_instance(
+        instance: &wgpu::Instance,
+        device_index: usize,
+    ) -> Result<Self, DeviceError> {
+        Self::from_adapter(&select_adapter(instance, device_index)?, Some(device_index))
+    }
+
+    /// Creates a device on the first adapter found, or returns `DeviceError::NoAdaptersFound`
+    /// instead of panicking when the machine has no usable GPU driver
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn require_any() -> Result<Self, DeviceError> {
+        let instance = create_instance(wgpu::Backends::PRIMARY);
+        let adapters = instance.enumerate_adapters(wgpu::Backends::PRIMARY);
+        let device = match adapters.first() {
+            Some(adapter) => Self::from_adapter(adapter, Some(0))?,
+            None => return Err(DeviceError::NoAdaptersFound),
+        };
+        Ok(device.owning(instance))
+    }
+
+    /// Like `require_any`, but when the primary backends have no adapter, e.g. on a headless
+    /// CI machine, falls back to the secondary backend (GL), preferring a software adapter
+    /// such as llvmpipe. Compute then runs, slowly, on the CPU. The adapter
+    /// still has to support `Features::MAPPABLE_PRIMARY_BUFFERS`, otherwise this fails with
+    /// `DeviceError::RequestDeviceFailed`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new_fallback() -> Result<Self, DeviceError> {
+        match Self::require_any() {
+            Err(DeviceError::NoAdaptersFound) => {}
+            result => return result,
+        }
+        let instance = create_instance(wgpu::Backends::SECONDARY);
+        let mut adapters = instance.enumerate_adapters(wgpu::Backends::SECONDARY);
+        adapters.sort_by_key(|adapter| adapter.get_info().device_type != wgpu::DeviceType::Cpu);
+        let device = match adapters.first() {
+            // The index of `Device::new` only covers the primary backends
+            Some(adapter) => Self::from_adapter(adapter, None)?,
+            None => return Err(DeviceError::NoAdaptersFound),
+        };
+        Ok(device.owning(instance))
+    }
+
+    /// Creates a device on the adapter the driver considers low power, typically the
+    /// integrated GPU of a dual-GPU laptop. The driver is free to pick another adapter,
+    /// check `DeviceInfo::is_low_power` to see what was actually selected.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn new_low_power() -> Result<Self, DeviceError> {
+        let instance = create_instance(wgpu::Backends::PRIMARY);
+        let adapter = block_on(instance.request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: wgpu::PowerPreference::LowPower,
+            force_fallback_adapter: false,
+            compatible_surface: None,
+        }))
+        .ok_or(DeviceError::NoAdaptersFound)?;
+        Self::from_adapter(&adapter, None).map(|device| device.owning(instance))
+    }
+
+    /// Keeps the `instance` the device was created from, which the poll thread polls
+    #[cfg(not(target_arch = "wasm32"))]
+    fn owning(mut self, instance: wgpu::Instance) -> Self {
+        self.instance = Some(Arc::new(instance));
+        self
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn from_adapter(adapter: &wgpu::Adapter, index: Option<usize>) -> Result<Self, DeviceError> {
+        // Optional features are enabled whenever the adapter has them
+        let features = wgpu::Features::MAPPABLE_PRIMARY_BUFFERS
+            | (adapter.features()
+                & (wgpu::Features::PUSH_CONSTANTS
+                    | wgpu::Features::TIMESTAMP_QUERY
+                    | wgpu::Features::SHADER_F64));
+        let limits = wgpu::Limits {
+            max_push_constant_size: adapter.limits().max_push_constant_size,
+            ..wgpu::Limits::default()
+        };
+        Self::request_device(adapter, index, features, limits)
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn request_device(
+        adapter: &wgpu::Adapter,
+        index: Option<usize>,
+        features: wgpu::Features,
+        limits: wgpu::Limits,
+    ) -> Result<Self, DeviceError> {
+        let (device, queue) = block_on(adapter.request_device(
+            &wgpu::DeviceDescriptor {
+                label: None,
+                required_features: features,
+                required_limits: limits,
+                memory_hints: wgpu::MemoryHints::default(),
+            },
+            None,
+        ))
+        .map_err(|_| DeviceError::RequestDeviceFailed)?;
+        let mut info = DeviceInfo::from_adapter(adapter, index);
+        info.limits = device.limits();
+        let lost = watch_device_lost(&device);
+
+        Ok(Device {
+            device,
+            queue,
+            info: Some(info),
+            readback_chunk_size: DEFAULT_READBACK_CHUNK_SIZE,
+            label: None,
+            allocated: Arc::new(AtomicU64::new(0)),
+            inflight: Inflight::default(),
+            trace: None,
+            specializations: SpecializationCache::default(),
+            lost,
+            buffer_pool: Arc::default(),
+            instance: None,
+            pending_maps: Arc::default(),
+            poll_thread: None,
+            map_pipelines: HashMap::new(),
+            ops_pipelines: HashMap::new(),
+        })
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    pub async fn new() -> Self {
+        let instance = create_instance(wgpu::Backends::PRIMARY);
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions::default())
+            .await
+            .unwrap();
+        let (device, queue) = adapter
+            .request_device(
+                &wgpu::DeviceDescriptor {
+                    label: None,
+                    // required_features: wgpu::Features::MAPPABLE_PRIMARY_BUFFERS,
+                    required_features: wgpu::Features::empty(),
+                    required_limits: wgpu::Limits::default(),
+                    memory_hints: wgpu::MemoryHints::default(),
+                },
+                None,
+            )
+            .await
+            .unwrap();
+        let lost = watch_device_lost(&device);
+
+        Device {
+            device,
+            queue,
+            readback_chunk_size: DEFAULT_READBACK_CHUNK_SIZE,
+            label: None,
+            allocated: Arc::new(AtomicU64::new(0)),
+            specializations: SpecializationCache::default(),
+            lost,
+            buffer_pool: Arc::default(),
+            map_pipelines: HashMap::new(),
+            ops_pipelines: HashMap::new(),
+        }
+    }
+
+    /// Sets a prefix for the labels of every buffer, pipeline and bind group created by this
+    /// device, so resources of different devices can be told apart in debugging tools
+    pub fn with_label(mut self, label: &str) -> Self {
+        self.label = Some(label.to_string());
+        self
+    }
+
+    /// `name` prefixed with the label of the device, if any
+    pub(crate) fn resource_label(&self, name: &str) -> String {
+        match &self.label {
+            Some(prefix) => format!("{} {}", prefix, name),
+            None => name.to_string(),
+        }
+    }
+
+    /// Uploads `data` into a new storage buffer.
+    ///
+    /// Buffers of different element types can be bound side by side in one dispatch, e.g. f16
+    /// inputs uploaded as their `u16` bits (read in GLSL with `unpackHalf2x16`) accumulated
+    /// into an `f32` output. Buffers are padded to a multiple of 4 bytes on the device, so
+    /// 2-byte element types may have an odd length.
+    /// ```ignore
+    ///     let halves: Vec<u16> = vec![0x3C00, 0x4000, 0x3800];
+    ///     let input = device.to_device(halves.as_slice());
+    ///     let output = device.to_device(&[0f32; 2]);
+    ///     let args = alkomp::ParamsBuilder::new()
+    ///         .param(Some(&input))
+    ///         .param(Some(&output))
+    ///         .build(Some(0));
+    /// ```
+    pub fn to_device<T: bytemuck::Pod>(&mut self, data: &[T]) -> GPUData<[T]> {
+        self.upload(data, STORAGE_USAGE, None)
+    }
+
+    /// Like `to_device`, but names the buffers after `label`, e.g. "weights Storage Buffer",
+    /// so validation errors and captures in tools like RenderDoc identify them. Labeled
+    /// buffers are not pooled, so a reused buffer never carries a stale name.
+    pub fn to_device_labeled<T: bytemuck::Pod>(&mut self, data: &[T], label: &str) -> GPUData<[T]> {
+        self.upload(data, STORAGE_USAGE, Some(label))
+    }
+
+    /// Like `to_device`, but the buffer can also be bound as a uniform buffer, e.g. with
+    /// `ParamsBuilder::uniform_dynamic`
+    pub fn to_device_uniform<T: bytemuck::Pod>(&mut self, data: &[T]) -> GPUData<[T]> {
+        self.upload(data, STORAGE_USAGE | wgpu::BufferUsages::UNIFORM, None)
+    }
+
+    /// Converts `data` to half precision and uploads it, halving the size of the buffer.
+    /// `GPUData<[half::f16]>` is read back with `get` like any other buffer.
+    ///
+    /// Shaders can read the values as `uint[]` and convert pairs with `unpackHalf2x16` and
+    /// `packHalf2x16`, which needs no device feature. Declaring `float16_t` buffers directly
+    /// requires `Features::SHADER_FLOAT16`, which isn't enabled by default; request it with
+    /// `Device::new_with` where the adapter supports it.
+    #[cfg(feature = "half")]
+    pub fn to_device_f16(&mut self, data: &[f32]) -> GPUData<[half::f16]> {
+        let halves: Vec<half::f16> = data.iter().map(|x| half::f16::from_f32(*x)).collect();
+        self.to_device(halves.as_slice())
+    }
+
+    /// Like `to_device`, but the storage buffer can be mapped by `get` directly instead of
+    /// being copied to the staging buffer first, halving the memory traffic of a readback.
+    /// Relies on `Features::MAPPABLE_PRIMARY_BUFFERS`, which native devices always enable.
+    /// The storage buffer may be placed in slower host-visible memory, so this suits buffers
+    /// read back often rather than ones mostly used by kernels.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn to_device_mappable<T: bytemuck::Pod>(&mut self, data: &[T]) -> GPUData<[T]> {
+        self.upload(data, STORAGE_USAGE | wgpu::BufferUsages::MAP_READ, None)
+    }
+
+    /// Like `to_device`, but the buffer can also hold the workgroup counts of
+    /// `call_indirect`, so a kernel can write the size of a later dispatch
+    pub fn to_device_indirect(&mut self, data: &[u32]) -> GPUData<[u32]> {
+        self.upload(data, STORAGE_USAGE | wgpu::BufferUsages::INDIRECT, None)
+    }
+
+    fn upload<T: bytemuck::Pod>(
+        &mut self,
+        data: &[T],
+        usage: wgpu::BufferUsages,
+        name: Option<&str>,
+    ) -> GPUData<[T]> {
+        let gpu = self.create_data(data, usage, name);
+
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            let mut encoder = self
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            encoder.copy_buffer_to_buffer(
+                &gpu.staging_buffer,
+                0,
+                &gpu.storage_buffer,
+                0,
+                gpu.padded_size(),
+            );
+
+            self.queue.submit(Some(encoder.finish()));
+        }
+        gpu
+    }
+
+    /// Overwrites the contents of `gpu` with `data` without allocating new buffers, e.g. to
+    /// re-upload the inputs of an iterative solver every step. `data` must have the same
+    /// length as the data `gpu` was created with.
+    pub fn update<T: bytemuck::Pod>(&mut self, gpu: &GPUData<[T]>, data: &[T]) {
+        let bytes: &[u8] = bytemuck::cast_slice(data);
+        assert_eq!(
+            bytes.len() as u64,
+            gpu.size,
+            "update needs data of the same size as the GPUData"
+        );
+        // Writes have to be a multiple of 4 bytes, which the buffers are padded to
+        if (bytes.len() as u64).is_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT) {
+            self.queue.write_buffer(&gpu.storage_buffer, 0, bytes);
+        } else {
+            let mut padded = bytes.to_vec();
+            padded.resize(gpu.padded_size() as usize, 0);
+            self.queue.write_buffer(&gpu.storage_buffer, 0, &padded);
+        }
+    }
+
+    /// Sets a counter created with `ParamsBuilder::counter` back to zero, reusing its buffer
+    pub fn reset_counter(&mut self, counter: &GPUData<[u32]>) {
+        self.update(counter, &[0]);
+    }
+
+    /// Zeroes `gpu` on the device with a clear command, e.g. to reset an accumulator between
+    /// dispatches, without uploading zeros from the host like `update` would. The clear needs
+    /// `COPY_DST`, which the storage buffers of all `GPUData` are created with; the padding
+    /// past `size` is zeroed as well.
+    pub fn clear<T: bytemuck::Pod>(&mut self, gpu: &GPUData<[T]>) {
+        if gpu.padded_size() == 0 {
+            return;
+        }
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.clear_buffer(&gpu.storage_buffer, 0, None);
+        self.queue.submit(Some(encoder.finish()));
+    }
+
+    /// Whether the device enabled `Features::MAPPABLE_PRIMARY_BUFFERS`, i.e. whether the CPU
+    /// and GPU share the memory of uploads. Native devices always enable it; on the web
+    /// every upload and readback goes through a separate staging copy.
+    pub fn supports_mappable_primary(&self) -> bool {
+        self.device
+            .features()
+            .contains(wgpu::Features::MAPPABLE_PRIMARY_BUFFERS)
+    }
+
+    /// Creates the buffers of a `GPUData` holding `data`, named after `name` if given, else
+    /// taken from the pool when possible. On native `data` is only in the staging buffer
+    /// until it is copied to the storage buffer.
+    fn create_data<T: bytemuck::Pod>(
+        &mut self,
+        data: &[T],
+        usage: wgpu::BufferUsages,
+        name: Option<&str>,
+    ) -> GPUData<[T]> {
+        let bytes = bytemuck::cast_slice(data);
+        let padded_size = align_to(bytes.len() as u64, wgpu::COPY_BUFFER_ALIGNMENT);
+        let prefix = name.map_or(String::new(), |name| format!("{} ", name));
+        let staging_label = self.resource_label(&format!("{}Staging Buffer", prefix));
+        let storage_label = self.resource_label(&format!("{}Storage Buffer", prefix));
+
+        let pooled_staging = match self.buffer_pool.lock() {
+            Ok(mut pool) if name.is_none() => {
+                let staging = pool.staging.get_mut(&padded_size).and_then(Vec::pop);
+                if staging.is_some() {
+                    pool.bytes -= padded_size;
+                }
+                staging
+            }
+            _ => None,
+        };
+
+        // On native we can share memory between CPU and GPU... but not in web
+        let staging_buffer = if let Some(buffer) = pooled_staging {
+            if cfg!(not(target_arch = "wasm32")) {
+                let mut padded = bytes.to_vec();
+                padded.resize(padded_size as usize, 0);
+                self.queue.write_buffer(&buffer, 0, &padded);
+            }
+            buffer
+        } else if cfg!(not(target_arch = "wasm32")) {
+            Arc::new(
+                self.device
+                    .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                        label: Some(&staging_label),
+                        contents: bytes,
+                        usage: wgpu::BufferUsages::MAP_READ
+                            | wgpu::BufferUsages::COPY_DST
+                            | wgpu::BufferUsages::COPY_SRC,
+                    }),
+            )
+        } else {
+            Arc::new(self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(&staging_label),
+                size: padded_size,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }))
+        };
+
+        let storage_buffer = if cfg!(not(target_arch = "wasm32")) {
+            self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(&storage_label),
+                size: padded_size,
+                usage,
+                mapped_at_creation: false,
+            })
+        } else {
+            // Preload the buffer with data if we are on web
+            self.device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(&storage_label),
+                    contents: bytes,
+                    usage,
+                })
+        };
+
+        GPUData {
+            staging_buffer,
+            storage_buffer,
+            size: bytes.len() as u64,
+            usage,
+            allocation: Allocation::new(&self.allocated, 2 * padded_size),
+            phantom: PhantomData,
+            pool: match name {
+                None => Some(self.buffer_pool.clone()),
+                Some(_) => None,
+            },
+            map_pending: AtomicBool::new(false),
+        }
+    }
+
+    /// Creates a `GPUData` of `len` elements without uploading anything, for outputs that
+    /// are entirely written on the device. The contents are undefined until then; use
+    /// `alloc_zeroed` for a buffer starting out as zeros.
+    pub fn alloc<T: bytemuck::Pod>(&mut self, len: usize) -> GPUData<[T]> {
+        self.create_empty((len * std::mem::size_of::<T>()) as u64, STORAGE_USAGE)
+    }
+
+    /// Like `alloc`, but clears the buffer to zeros with `clear`
+    pub fn alloc_zeroed<T: bytemuck::Pod>(&mut self, len: usize) -> GPUData<[T]> {
+        let gpu = self.alloc(len);
+        self.clear(&gpu);
+        gpu
+    }
+
+    /// Creates the buffers of a `GPUData` of `size` bytes with a storage buffer of `usage`,
+    /// leaving their contents undefined
+    fn create_empty<T: bytemuck::Pod>(
+        &mut self,
+        size: u64,
+        usage: wgpu::BufferUsages,
+    ) -> GPUData<[T]> {
+        let padded_size = align_to(size, wgpu::COPY_BUFFER_ALIGNMENT);
+        let staging_label = self.resource_label("Staging Buffer");
+        let storage_label = self.resource_label("Storage Buffer");
+
+        let staging_buffer = Arc::new(self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&staging_label),
+            size: padded_size,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        }));
+
+        let storage_buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&storage_label),
+            size: padded_size,
+            usage,
+            mapped_at_creation: false,
+        });
+
+        GPUData {
+            staging_buffer,
+            storage_buffer,
+            size,
+            usage,
+            allocation: Allocation::new(&self.allocated, 2 * padded_size),
+            phantom: PhantomData,
+            // Without COPY_SRC this staging buffer can't serve uploads
+            pool: None,
+            map_pending: AtomicBool::new(false),
+        }
+    }
+
+    /// Copies the storage buffer of `gpu` back to the host.
+    ///
+    /// The copy is submitted after any earlier `call`, and wgpu inserts the memory barrier
+    /// between a dispatch's storage writes and a later copy out of the same buffer, even
+    /// across submissions, so the data read back always includes writes of prior dispatches.
+    ///
+    /// The staging buffer is created with `MAP_READ`, which wgpu already places in host-visible
+    /// memory the GPU copies into directly: page-locked readback memory on Vulkan, DX12 and
+    /// Metal. There is no faster pinned path to opt into; the one host-side copy left is the
+    /// one out of the mapping into the returned slice.
+    ///
+    /// Fails with `GetError::NotReadable` for buffers without `COPY_SRC`, and with
+    /// `GetError::DeviceLost` rather than `MapFailed` when the device is gone.
+    pub async fn get<T>(&mut self, gpu: &GPUData<[T]>) -> Result<Box<[T]>, GetError>
+    where
+        T: bytemuck::Pod,
+    {
+        if !gpu.is_readable() {
+            return Err(GetError::NotReadable);
+        }
+        // Mappable storage buffers are read directly, skipping the copy to the staging buffer
+        let buffer = if gpu.is_mappable() {
+            // Nothing else is submitted, but writes queued by `update` only land on a submit
+            self.queue.submit(None);
+            &gpu.storage_buffer
+        } else {
+            self.copy_to_staging(gpu);
+            if gpu.size > self.readback_chunk_size {
+                return self.get_chunked(gpu).await;
+            }
+            &gpu.staging_buffer
+        };
+
+        let buffer_future = map_read(buffer.slice(0..gpu.padded_size()));
+
+        self.device.poll(wgpu::Maintain::Wait);
+
+        // Gets contents of buffer
+        match buffer_future.await {
+            Ok(()) => Ok(read_mapped(buffer, gpu)),
+            Err(error) => Err(self.map_error(error)),
+        }
+    }
+
+    /// Like `get`, but blocks until the data is read back instead of returning a future
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn get_blocking<T>(&mut self, gpu: &GPUData<[T]>) -> Result<Box<[T]>, GetError>
+    where
+        T: bytemuck::Pod,
+    {
+        block_on(self.get(gpu))
+    }
+
+    /// Like `get`, but never polls the device, so awaiting it doesn't block the thread. The
+    /// copy is submitted and the mapping requested right away; the returned future resolves
+    /// once something drives the device, e.g. `Device::poll(false)` called from a timer or a
+    /// background thread of an async runtime. The whole buffer is mapped at once, regardless
+    /// of `readback_chunk_size`.
+    ///
+    /// Dropping the future before it resolves unmaps the buffer, which cancels the pending
+    /// map, so `gpu` can be read again right away.
+    /// ```ignore
+    ///     let pending = device.readback_async(&data_gpu);
+    ///     device.poll(true);
+    ///     let data = pending.await.unwrap();
+    /// ```
+    pub fn readback_async<'g, T>(
+        &mut self,
+        gpu: &'g GPUData<[T]>,
+    ) -> impl std::future::Future<Output = Result<Box<[T]>, GetError>> + 'g
+    where
+        T: bytemuck::Pod,
+    {
+        let readable = gpu.is_readable();
+        let buffer: &wgpu::Buffer = if gpu.is_mappable() {
+            self.queue.submit(None);
+            &gpu.storage_buffer
+        } else {
+            if readable {
+                self.copy_to_staging(gpu);
+            }
+            &gpu.staging_buffer
+        };
+        let buffer_future = if readable {
+            Some(self.map_read_tracked(buffer.slice(0..gpu.padded_size())))
+        } else {
+            None
+        };
+        let pending = match buffer_future {
+            Some(_) => PendingMap::arm(buffer, &gpu.map_pending),
+            None => PendingMap(None),
+        };
+        let lost = self.lost.clone();
+
+        async move {
+            let buffer_future = buffer_future.ok_or(GetError::NotReadable)?;
+            let result = buffer_future.await;
+            pending.disarm();
+            match result {
+                Ok(()) => Ok(read_mapped(buffer, gpu)),
+                Err(_) if lost.load(Ordering::SeqCst) => Err(GetError::DeviceLost),
+                Err(error) => Err(GetError::MapFailed(error)),
+            }
+        }
+    }
+
+    /// Like `map_read`, but counts the map as pending until its callback fires, to wake the
+    /// thread of `spawn_poll_thread`
+    fn map_read_tracked(
+        &self,
+        slice: wgpu::BufferSlice<'_>,
+    ) -> impl std::future::Future<Output = Result<(), wgpu::BufferAsyncError>> {
+        #[cfg(not(target_arch = "wasm32"))]
+        let done = {
+            let pending_maps = self.pending_maps.clone();
+            pending_maps.add();
+            move || pending_maps.remove()
+        };
+        #[cfg(target_arch = "wasm32")]
+        let done = || {};
+        map_read_then(slice, done)
+    }
+
+    /// Tells a failed map of a lost device apart from other failures
+    fn map_error(&self, error: wgpu::BufferAsyncError) -> GetError {
+        if self.lost.load(Ordering::SeqCst) {
+            GetError::DeviceLost
+        } else {
+            GetError::MapFailed(error)
+        }
+    }
+
+    /// Reads the staging buffer back into a new slice, through `read_chunks`
+    async fn get_chunked<T>(&mut self, gpu: &GPUData<[T]>) -> Result<Box<[T]>, GetError>
+    where
+        T: bytemuck::Pod,
+    {
+        let mut result = vec![T::zeroed(); gpu.len()];
+        self.read_chunks(gpu, 0, bytemuck::cast_slice_mut(&mut result), None)
+            .await?;
+        Ok(result.into_boxed_slice())
+    }
+
+    /// Reads back only the elements in `dirty` into `prev`, keeping the rest of `prev` from an
+    /// earlier read. If `prev` does not hold as many elements as `gpu` the whole buffer is read
+    /// instead. Fails with `GetError::NotReadable` for buffers without `COPY_SRC`.
+    pub async fn get_incremental<T>(
+        &mut self,
+        gpu: &GPUData<[T]>,
+        dirty: std::ops::Range<usize>,
+        prev: &mut Vec<T>,
+    ) -> Result<(), GetError>
+    where
+        T: bytemuck::Pod,
+    {
+        let elem = std::mem::size_of::<T>();
+        let len = gpu.len();
+        if prev.len() != len {
+            *prev = self.get(gpu).await?.into_vec();
+            return Ok(());
+        }
+        if !gpu.is_readable() {
+            return Err(GetError::NotReadable);
+        }
+        let start = dirty.start * elem;
+        let end = (dirty.end * elem).min(gpu.size as usize);
+        if start >= end {
+            return Ok(());
+        }
+        let out: &mut [u8] = bytemuck::cast_slice_mut(prev.as_mut_slice());
+        self.read_bytes(gpu, start as u64, &mut out[start..end])
+            .await
+    }
+
+    /// Copies back only the elements in `range` of `gpu`, instead of the whole buffer like
+    /// `get`. Fails with `GetError::OutOfBounds` if `range` doesn't lie within `gpu`.
+    pub async fn get_range<T>(
+        &mut self,
+        gpu: &GPUData<[T]>,
+        range: std::ops::Range<usize>,
+    ) -> Result<Box<[T]>, GetError>
+    where
+        T: bytemuck::Pod,
+    {
+        let elem = std::mem::size_of::<T>();
+        if range.start > range.end || range.end > gpu.len() {
+            return Err(GetError::OutOfBounds {
+                start: range.start,
+                end: range.end,
+                len: gpu.len(),
+            });
+        }
+        if !gpu.is_readable() {
+            return Err(GetError::NotReadable);
+        }
+        let mut result = vec![T::zeroed(); range.len()];
+        let out: &mut [u8] = bytemuck::cast_slice_mut(result.as_mut_slice());
+        if !out.is_empty() {
+            self.read_bytes(gpu, (range.start * elem) as u64, out)
+                .await?;
+        }
+        Ok(result.into_boxed_slice())
+    }
+
+    /// Like `get`, but copies into `out` instead of allocating a new slice on every call, e.g.
+    /// to poll a buffer in a loop. `out` must hold exactly as many elements as `gpu`.
+    pub async fn get_into<T>(&mut self, gpu: &GPUData<[T]>, out: &mut [T]) -> Result<(), GetError>
+    where
+        T: bytemuck::Pod,
+    {
+        if !gpu.is_readable() {
+            return Err(GetError::NotReadable);
+        }
+        let expected = gpu.len();
+        if out.len() != expected {
+            return Err(GetError::SizeMismatch {
+                expected,
+                found: out.len(),
+            });
+        }
+        self.read_bytes(gpu, 0, bytemuck::cast_slice_mut(out)).await
+    }
+
+    /// Copies `out.len()` bytes of the storage buffer of `gpu` from byte `start` into `out`,
+    /// through the matching range of the staging buffer
+    async fn read_bytes<T>(
+        &mut self,
+        gpu: &GPUData<[T]>,
+        start: u64,
+        out: &mut [u8],
+    ) -> Result<(), GetError> {
+        let end = start + out.len() as u64;
+        // Copies need 4 byte aligned sizes and mappings 8 byte aligned offsets
+        let mapped_start = start - start % MAP_ALIGNMENT;
+        let mapped_end = align_to(end, wgpu::COPY_BUFFER_ALIGNMENT);
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_buffer_to_buffer(
+            &gpu.storage_buffer,
+            mapped_start,
+            &gpu.staging_buffer,
+            mapped_start,
+            mapped_end - mapped_start,
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        self.read_chunks(gpu, start, out, None).await
+    }
+
+    /// Copies `out.len()` bytes of the staging buffer of `gpu` from byte `start` into `out`,
+    /// mapping one `readback_chunk_size` range at a time for drivers that limit the size of a
+    /// single mapping. With a `token` the wait on each chunk polls the device and gives up with
+    /// `GetError::Cancelled` once the token is cancelled, unmapping the pending chunk.
+    async fn read_chunks<T>(
+        &mut self,
+        gpu: &GPUData<[T]>,
+        start: u64,
+        out: &mut [u8],
+        token: Option<&CancelToken>,
+    ) -> Result<(), GetError> {
+        let len = out.len() as u64;
+        let mut offset = start;
+        while offset < start + len {
+            // Mappings need 8 byte aligned offsets and 4 byte aligned sizes
+            let mapped_start = offset - offset % MAP_ALIGNMENT;
+            let end = (mapped_start + self.readback_chunk_size).min(start + len);
+            let mapped_end = align_to(end, wgpu::COPY_BUFFER_ALIGNMENT);
+
+            let buffer_slice = gpu.staging_buffer.slice(mapped_start..mapped_end);
+            let buffer_future = map_read(buffer_slice);
+            let mapped = match token {
+                Some(token) => {
+                    let pending = PendingMap::arm(&gpu.staging_buffer, &gpu.map_pending);
+                    futures::pin_mut!(buffer_future);
+                    let mapped = loop {
+                        if token.is_cancelled() {
+                            return Err(Cancelled.into());
+                        }
+                        self.device.poll(wgpu::Maintain::Poll);
+                        if let Some(mapped) = buffer_future.as_mut().now_or_never() {
+                            break mapped;
+                        }
+                        std::thread::yield_now();
+                    };
+                    pending.disarm();
+                    mapped
+                }
+                None => {
+                    self.device.poll(wgpu::Maintain::Wait);
+                    buffer_future.await
+                }
+            };
+            if let Err(error) = mapped {
+                return Err(self.map_error(error));
+            }
+            let data = buffer_slice.get_mapped_range();
+            out[(offset - start) as usize..(end - start) as usize].copy_from_slice(
+                &data[(offset - mapped_start) as usize..(end - mapped_start) as usize],
+            );
+            drop(data);
+            gpu.staging_buffer.unmap();
+
+            offset = end;
+        }
+        Ok(())
+    }
+
+    /// Sets the size in bytes above which `get` reads a buffer back in several smaller mappings.
+    /// The size is rounded down to the map alignment of 8 bytes.
+    pub fn set_readback_chunk_size(&mut self, bytes: u64) {
+        self.readback_chunk_size = (bytes - bytes % MAP_ALIGNMENT).max(MAP_ALIGNMENT);
+    }
+
+    pub fn readback_chunk_size(&self) -> u64 {
+        self.readback_chunk_size
+    }
+
+    /// Like `get`, but stops waiting on the readback as soon as `token` is cancelled and
+    /// fails with `GetError::Cancelled`.
+    ///
+    /// Like `get`, buffers larger than `readback_chunk_size` are mapped one chunk at a time,
+    /// and the token is checked while waiting on each chunk.
+    ///
+    /// Only the wait is abandoned: work already submitted to the GPU still runs to completion.
+    /// A cancel unmaps the staging buffer, which cancels its pending map, so `gpu` can be read
+    /// again right away; the contents of the staging buffer are indeterminate until then.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn get_cancellable<T>(
+        &mut self,
+        gpu: &GPUData<[T]>,
+        token: &CancelToken,
+    ) -> Result<Box<[T]>, GetError>
+    where
+        T: bytemuck::Pod,
+    {
+        if token.is_cancelled() {
+            return Err(Cancelled.into());
+        }
+        if !gpu.is_readable() {
+            return Err(GetError::NotReadable);
+        }
+        self.copy_to_staging(gpu);
+
+        let mut result = vec![T::zeroed(); gpu.len()];
+        block_on(self.read_chunks(gpu, 0, bytemuck::cast_slice_mut(&mut result), Some(token)))?;
+        Ok(result.into_boxed_slice())
+    }
+
+    /// Times an upload with `to_device` and a readback with `get` of a 64 MiB buffer,
+    /// to tell whether a workload is limited by host/device transfers. Fails like `get` if
+    /// the buffer can't be read back.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn measure_bandwidth(&mut self) -> Result<BandwidthReport, GetError> {
+        const BYTES: usize = 64 << 20;
+        let data = vec![0u32; BYTES / std::mem::size_of::<u32>()];
+
+        let start = std::time::Instant::now();
+        let gpu = self.to_device(data.as_slice());
+        self.device.poll(wgpu::Maintain::Wait);
+        let upload = start.elapsed();
+
+        let start = std::time::Instant::now();
+        block_on(self.get(&gpu))?;
+        let download = start.elapsed();
+
+        let mb = BYTES as f64 / 1e6;
+        Ok(BandwidthReport {
+            bytes: BYTES as u64,
+            host_to_device_mb_s: mb / upload.as_secs_f64(),
+            device_to_host_mb_s: mb / download.as_secs_f64(),
+        })
+    }
+
+    /// Sum of the bytes allocated by all live `GPUData` created by this device, staging and
+    /// storage buffers included, plus the `pooled_bytes` kept for reuse. Useful to budget
+    /// memory and detect leaked buffers.
+    pub fn total_allocated_bytes(&self) -> u64 {
+        self.allocated.load(Ordering::SeqCst) + self.pooled_bytes()
+    }
+
+    /// Bytes held by the buffers of dropped `GPUData`, kept so uploads of the same size can
+    /// reuse them instead of allocating. At most the budget of `set_pool_budget`.
+    pub fn pooled_bytes(&self) -> u64 {
+        self.buffer_pool.lock().map_or(0, |pool| pool.bytes)
+    }
+
+    /// Limits the bytes of dropped buffers kept for reuse, `DEFAULT_POOL_BUDGET` by default.
+    /// Buffers that would exceed it are freed instead; 0 disables pooling. The pool is
+    /// cleared if it already holds more than `bytes`.
+    pub fn set_pool_budget(&self, bytes: u64) {
+        if let Ok(mut pool) = self.buffer_pool.lock() {
+            pool.budget = bytes;
+            if pool.bytes > bytes {
+                pool.clear();
+            }
+        }
+    }
+
+    /// Releases the pooled buffers, e.g. after a streaming phase whose chunk size won't be
+    /// uploaded again
+    pub fn clear_pool(&self) {
+        if let Ok(mut pool) = self.buffer_pool.lock() {
+            pool.clear();
+        }
+    }
+
+    /// Limits how many dispatches may be submitted but not yet completed. Once `max` are
+    /// outstanding, `call` blocks until the oldest finishes, so producers that submit faster
+    /// than the GPU drains can't queue up unbounded work. `None`, the default, never blocks.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_max_inflight(&mut self, max: Option<usize>) {
+        self.inflight.max = max.map(|n| n.max(1));
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn max_inflight(&self) -> Option<usize> {
+        self.inflight.max
+    }
+
+    /// Number of dispatches submitted since a limit was set with `set_max_inflight` that have
+    /// not completed yet
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn inflight(&self) -> usize {
+        self.device.poll(wgpu::Maintain::Poll);
+        self.inflight.outstanding()
+    }
+
+    /// Waits for the oldest dispatches until fewer than the maximum are inflight
+    #[cfg(not(target_arch = "wasm32"))]
+    fn throttle(&mut self) {
+        if let Some(max) = self.inflight.max {
+            self.device.poll(wgpu::Maintain::Poll);
+            self.inflight.retire_completed();
+            while self.inflight.submissions.len() >= max {
+                let oldest = self.inflight.submissions.pop_front().unwrap();
+                self.device
+                    .poll(wgpu::Maintain::WaitForSubmissionIndex(oldest));
+                self.inflight.retire_completed();
+            }
+        }
+    }
+
+    /// Counts a submitted dispatch as inflight until its work is done, if a limit is set
+    #[cfg(not(target_arch = "wasm32"))]
+    fn track_inflight(&mut self, index: &wgpu::SubmissionIndex) {
+        if self.inflight.max.is_some() {
+            let completed = self.inflight.completed.clone();
+            self.queue.on_submitted_work_done(move || {
+                completed.fetch_add(1, Ordering::SeqCst);
+            });
+            self.inflight.submissions.push_back(index.clone());
+            self.inflight.submitted += 1;
+        }
+    }
+
+    /// Starts or stops recording the time of every dispatch for `export_trace`.
+    ///
+    /// With `Features::TIMESTAMP_QUERY` every pass is timed on the GPU by its own timestamp
+    /// queries, which are only read back by `export_trace`, so dispatches still overlap.
+    /// Otherwise each dispatch waits until the GPU has finished it, so dispatches don't
+    /// overlap and their wall-clock time from submission to completion is what the GPU
+    /// spent on them.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn set_tracing(&mut self, enabled: bool) {
+        if !enabled {
+            self.trace = None;
+        } else if self.trace.is_none() {
+            self.trace = Some(Trace {
+                start: std::time::Instant::now(),
+                events: Vec::new(),
+                timed_passes: Mutex::default(),
+            });
+        }
+    }
+
+    /// Writes the dispatches recorded since `set_tracing(true)` to `path` in the Chrome trace
+    /// event format, one event named after the entry point per dispatch, to be opened in
+    /// `chrome://tracing` or Perfetto
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn export_trace<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        let mut events = vec![];
+        if let Some(trace) = &self.trace {
+            events.extend(trace.events.iter().cloned());
+            events.extend(self.timed_events(trace));
+        }
+        let events: Vec<String> = events
+            .iter()
+            .map(|event| {
+                format!(
+                    concat!(
+                        "{{\"name\":\"{}\",\"cat\":\"dispatch\",\"ph\":\"X\",",
+                        "\"ts\":{},\"dur\":{},\"pid\":0,\"tid\":0}}"
+                    ),
+                    event.name.replace('\\', "\\\\").replace('"', "\\\""),
+                    event.start_us,
+                    event.duration_us
+                )
+            })
+            .collect();
+        std::fs::write(
+            path,
+            format!("{{\"traceEvents\":[\n{}\n]}}\n", events.join(",\n")),
+        )
+    }
+
+    /// Reads back the timestamps of the passes traced with timestamp queries, as events
+    /// relative to the earliest of them
+    #[cfg(not(target_arch = "wasm32"))]
+    fn timed_events(&self, trace: &Trace) -> Vec<TraceEvent> {
+        let passes = trace.timed_passes.lock().unwrap();
+        let ticks: Vec<(&str, u64, u64)> = passes
+            .iter()
+            .filter_map(|pass| {
+                let (start, end) = pass.read(&self.device)?;
+                Some((pass.name.as_str(), start, end))
+            })
+            .collect();
+        let first = ticks.iter().map(|(_, start, _)| *start).min().unwrap_or(0);
+        let us_per_tick = self.queue.get_timestamp_period() as f64 / 1000.0;
+        ticks
+            .into_iter()
+            .map(|(name, start, end)| TraceEvent {
+                name: name.to_string(),
+                start_us: (start - first) as f64 * us_per_tick,
+                duration_us: end.saturating_sub(start) as f64 * us_per_tick,
+            })
+            .collect()
+    }
+
+    /// Whether passes can be timed with timestamp queries, see `call_timed`
+    #[cfg(not(target_arch = "wasm32"))]
+    fn timestamp_queries(&self) -> bool {
+        self.device
+            .features()
+            .contains(wgpu::Features::TIMESTAMP_QUERY)
+    }
+
+    /// The query set and buffers to time one pass of `entry` with
+    #[cfg(not(target_arch = "wasm32"))]
+    fn timed_pass(&self, entry: &str) -> TimedPass {
+        TimedPass {
+            name: entry.to_string(),
+            query_set: self.device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some(&self.resource_label("Timestamps")),
+                ty: wgpu::QueryType::Timestamp,
+                count: 2,
+            }),
+            resolve_buffer: self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(&self.resource_label("Timestamp Resolve Buffer")),
+                size: TIMESTAMPS_SIZE,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            readback_buffer: self.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(&self.resource_label("Timestamp Readback Buffer")),
+                size: TIMESTAMPS_SIZE,
+                usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+                mapped_at_creation: false,
+            }),
+        }
+    }
+
+    /// Waits for the dispatch of `entry` and records how long it took, if tracing without
+    /// timestamp queries
+    #[cfg(not(target_arch = "wasm32"))]
+    fn trace_dispatch(
+        &mut self,
+        entry: &str,
+        started: std::time::Instant,
+        index: &wgpu::SubmissionIndex,
+    ) {
+        if self.trace.is_none() || self.timestamp_queries() {
+            return;
+        }
+        self.wait_for(index.clone());
+        let duration = started.elapsed();
+        if let Some(trace) = &mut self.trace {
+            trace.events.push(TraceEvent {
+                name: entry.to_string(),
+                start_us: started.duration_since(trace.start).as_secs_f64() * 1e6,
+                duration_us: duration.as_secs_f64() * 1e6,
+            });
+        }
+    }
+
+    /// Hints that `gpu` will be reused by many dispatches and should stay resident on the device.
+    ///
+    /// wgpu doesn't expose residency control on any backend yet, so this is currently a no-op
+    /// everywhere: wgpu never migrates storage buffers back to the host on its own anyway.
+    /// It exists so performance-sensitive code can state the intent today.
+    pub fn touch<T: ?Sized>(&self, _gpu: &GPUData<T>) {}
+
+    /// Copies the storage buffer of `src` into `dst` on the device, without a round trip
+    /// through the host, e.g. to snapshot a buffer later dispatches keep modifying. Only as
+    /// many elements as the smaller of the two holds are copied; with elements smaller than
+    /// 4 bytes the copy is rounded up to whole 4 byte words.
+    pub fn copy<T: bytemuck::Pod>(&mut self, src: &GPUData<[T]>, dst: &GPUData<[T]>) {
+        let size = src.padded_size().min(dst.padded_size());
+        if size > 0 {
+            self.copy_storage(&src.storage_buffer, 0, &dst.storage_buffer, size);
+        }
+    }
+
+    /// Allocates a new `GPUData` with the same length and usage as `src` and copies the
+    /// current contents of `src` into it on the device, e.g. to feed two pipelines that
+    /// modify their input differently. The copy sees the writes of every earlier `call`.
+    pub fn clone_gpu_data<T: bytemuck::Pod>(&mut self, src: &GPUData<[T]>) -> GPUData<[T]> {
+        let dst = self.create_empty(src.size, src.usage);
+        if dst.padded_size() > 0 {
+            self.copy_storage(
+                &src.storage_buffer,
+                0,
+                &dst.storage_buffer,
+                dst.padded_size(),
+            );
+        }
+        dst
+    }
+
+    /// Copies `size` bytes at `offset` of `src` to the start of `dst`
+    pub(crate) fn copy_storage(
+        &mut self,
+        src: &wgpu::Buffer,
+        offset: u64,
+        dst: &wgpu::Buffer,
+        size: u64,
+    ) {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_buffer_to_buffer(src, offset, dst, 0, size);
+        self.queue.submit(Some(encoder.finish()));
+    }
+
+    fn copy_to_staging<T>(&mut self, gpu: &GPUData<[T]>) {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_buffer_to_buffer(
+            &gpu.storage_buffer,
+            0,
+            &gpu.staging_buffer,
+            0,
+            gpu.padded_size(),
+        );
+        self.queue.submit(Some(encoder.finish()));
+    }
+
+    pub fn compile(
+        &self,
+        entry: &str,
+        shader: &[u32],
+        params: &GPUSetGroupLayout,
+    ) -> Result<GPUCompute, CompileError> {
+        self.compile_with_push_constants(entry, shader, params, 0)
+    }
+
+    /// Like `compile`, but declares a push-constant range of `push_constant_size` bytes for
+    /// the compute stage, which is then set per dispatch with `Device::dispatch` or
+    /// `Device::call_with_push_constants`. Fails with `CompileError::MissingFeature` if the
+    /// adapter doesn't support `Features::PUSH_CONSTANTS`.
+    pub fn compile_with_push_constants(
+        &self,
+        entry: &str,
+        shader: &[u32],
+        params: &GPUSetGroupLayout,
+        push_constant_size: u32,
+    ) -> Result<GPUCompute, CompileError> {
+        self.check_features(shader, push_constant_size)?;
+        let cs_module = self.create_spirv_module(shader);
+        Ok(self.build_spirv_pipeline(entry, &cs_module, shader, params, push_constant_size))
+    }
+
+    /// Fails with `CompileError::MissingFeature` if the device lacks a feature `shader` or its
+    /// push constants need
+    fn check_features(&self, shader: &[u32], push_constant_size: u32) -> Result<(), CompileError> {
+        if push_constant_size > 0
+            && !self
+                .device
+                .features()
+                .contains(wgpu::Features::PUSH_CONSTANTS)
+        {
+            return Err(CompileError::MissingFeature(wgpu::Features::PUSH_CONSTANTS));
+        }
+        if crate::spirv::uses_float64(shader)
+            && !self.device.features().contains(wgpu::Features::SHADER_F64)
+        {
+            return Err(CompileError::MissingFeature(wgpu::Features::SHADER_F64));
+        }
+        Ok(())
+    }
+
+    /// The pipeline of `entry` of a module created from `shader`, with the workgroup size and
+    /// read-only bindings reflected from the SPIR-V
+    fn build_spirv_pipeline(
+        &self,
+        entry: &str,
+        cs_module: &wgpu::ShaderModule,
+        shader: &[u32],
+        params: &GPUSetGroupLayout,
+        push_constant_size: u32,
+    ) -> GPUCompute {
+        let local_size = crate::spirv::workgroup_size(shader, entry);
+        let mut compute =
+            self.build_pipeline(entry, cs_module, local_size, params, push_constant_size);
+        compute.readonly = Some(crate::spirv::readonly_bindings(shader));
+        compute
+    }
+
+    /// Creates the shader module of `shader` once, so several entry points can be compiled
+    /// from it with `compile_entry` without creating and validating the module each time
+    pub fn load_module(&self, shader: &[u32]) -> ShaderModule {
+        let module = self.create_spirv_module(shader);
+        ShaderModule {
+            module,
+            spirv: shader.to_vec(),
+        }
+    }
+
+    /// Like `compile`, for an entry point of a module created with `load_module`
+    /// ```ignore
+    ///     let module = device.load_module(&shader);
+    ///     let map = device.compile_entry(&module, "main", &args.0).unwrap();
+    ///     let reduce = device.compile_entry(&module, "reduce", &args.0).unwrap();
+    /// ```
+    pub fn compile_entry(
+        &self,
+        module: &ShaderModule,
+        entry: &str,
+        params: &GPUSetGroupLayout,
+    ) -> Result<GPUCompute, CompileError> {
+        self.compile_entry_with_push_constants(module, entry, params, 0)
+    }
+
+    /// Like `compile_with_push_constants`, for an entry point of a module created with
+    /// `load_module`
+    pub fn compile_entry_with_push_constants(
+        &self,
+        module: &ShaderModule,
+        entry: &str,
+        params: &GPUSetGroupLayout,
+        push_constant_size: u32,
+    ) -> Result<GPUCompute, CompileError> {
+        self.check_features(&module.spirv, push_constant_size)?;
+        Ok(self.build_spirv_pipeline(
+            entry,
+            &module.module,
+            &module.spirv,
+            params,
+            push_constant_size,
+        ))
+    }
+
+    /// Compiles the compute entry point `entry` of a WGSL shader, for prototyping without an
+    /// offline SPIR-V toolchain, e.g. on the web where shaderc isn't available. Fails with
+    /// `CompileError::InvalidWgsl` if the source doesn't parse.
+    pub fn compile_wgsl(
+        &self,
+        entry: &str,
+        wgsl_source: &str,
+        params: &GPUSetGroupLayout,
+    ) -> Result<GPUCompute, CompileError> {
+        let local_size = wgsl_workgroup_size(wgsl_source, entry)?;
+        let cs_module = self
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(&self.resource_label(&format!("{} Shader Module", entry))),
+                source: wgpu::ShaderSource::Wgsl(std::borrow::Cow::Borrowed(wgsl_source)),
+            });
+        Ok(self.build_pipeline(entry, &cs_module, local_size, params, 0))
+    }
+
+    fn create_spirv_module(&self, shader: &[u32]) -> wgpu::ShaderModule {
+        self.device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(&self.resource_label("Shader Module")),
+                source: wgpu::ShaderSource::SpirV(std::borrow::Cow::Borrowed(shader)),
+            })
+    }
+
+    fn build_pipeline(
+        &self,
+        entry: &str,
+        cs_module: &wgpu::ShaderModule,
+        local_size: Option<(u32, u32, u32)>,
+        params: &GPUSetGroupLayout,
+        push_constant_size: u32,
+    ) -> GPUCompute {
+        let layout_label = self.resource_label(&format!("{} Bind Group Layout", entry));
+        let pipeline_label = self.resource_label(&format!("{} Pipeline", entry));
+        let mut bind_group_layouts: HashMap<u32, wgpu::BindGroupLayout> = HashMap::new();
+        let push_constant_ranges = [wgpu::PushConstantRange {
+            stages: wgpu::ShaderStages::COMPUTE,
+            range: 0..push_constant_size,
+        }];
+
+        for (set_id, set) in &params.set_bind_group_layouts {
+            bind_group_layouts.insert(
+                *set_id,
+                self.device
+                    .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                        label: Some(&layout_label),
+                        entries: sorted_by_binding(
+                            set.values()
+                                .map(|binding_layout| binding_layout.0)
+                                .collect(),
+                            |entry| entry.binding,
+                        )
+                        .as_slice(),
+                    }),
+            );
+        }
+
+        // The pipeline layout lists the sets by index, so unused sets below the highest one
+        // get an empty layout
+        let max_set = bind_group_layouts.keys().max().copied();
+        for set_id in max_set.map_or(0..0, |max| 0..max) {
+            bind_group_layouts.entry(set_id).or_insert_with(|| {
+                self.device
+                    .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                        label: Some(&layout_label),
+                        entries: &[],
+                    })
+            });
+        }
+        let mut ordered_layouts: Vec<(&u32, &wgpu::BindGroupLayout)> =
+            bind_group_layouts.iter().collect();
+        ordered_layouts.sort_by_key(|(set_id, _)| **set_id);
+
+        let pipeline_layout = self
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(&pipeline_label),
+                bind_group_layouts: ordered_layouts
+                    .iter()
+                    .map(|(_, layout)| *layout)
+                    .collect::<Vec<&wgpu::BindGroupLayout>>()
+                    .as_slice(),
+                push_constant_ranges: if push_constant_size > 0 {
+                    &push_constant_ranges
+                } else {
+                    &[]
+                },
+            });
+
+        let pipeline = self
+            .device
+            .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(&pipeline_label),
+                layout: Some(&pipeline_layout),
+                module: cs_module,
+                entry_point: entry,
+                compilation_options: wgpu::PipelineCompilationOptions::default(),
+                cache: None,
+            });
+
+        GPUCompute {
+            entry: entry.to_string(),
+            layouts: params.set_bind_group_layouts.clone(),
+            bind_group_layouts: Arc::new(bind_group_layouts),
+            compute_pipeline: Arc::new(pipeline),
+            local_size,
+            push_constant_size,
+            readonly: None,
+        }
+    }
+
+    /// Compiles `entry` of `shader` with its specialization constants frozen to `constants`,
+    /// given as `(constant_id, value)` pairs and converted to the type of each constant, see
+    /// `spirv::specialize`.
+    /// ```ignore
+    ///     // layout(local_size_x_id = 0) in; layout(constant_id = 1) const float SCALE = 1.0;
+    ///     let compute = device
+    ///         .compile_specialized("main", &shader, &layout, &[(0, 128.0), (1, 0.5)])
+    ///         .unwrap();
+    /// ```
+    ///
+    /// Pipelines are cached by shader, entry, layout and constants for the lifetime of the
+    /// device, so sweeping many configurations only compiles each one once. The cached
+    /// `GPUCompute` is shared with the returned clone.
+    pub fn compile_specialized(
+        &mut self,
+        entry: &str,
+        shader: &Vec<u32>,
+        params: &GPUSetGroupLayout,
+        constants: &[(u32, f64)],
+    ) -> Result<GPUCompute, CompileError> {
+        let mut constants: Vec<(u32, u64)> = constants
+            .iter()
+            .map(|(id, value)| (*id, value.to_bits()))
+            .collect();
+        constants.sort();
+        let key = SpecializationKey {
+            shader: hash_of(shader),
+            layout: layout_hash(params),
+            entry: entry.to_string(),
+            constants,
+        };
+        if let Some(compute) = self.specializations.pipelines.get(&key) {
+            self.specializations.stats.hits += 1;
+            return Ok(compute.clone());
+        }
+        self.specializations.stats.misses += 1;
+
+        let values: Vec<(u32, f64)> = key
+            .constants
+            .iter()
+            .map(|(id, bits)| (*id, f64::from_bits(*bits)))
+            .collect();
+        let specialized = crate::spirv::specialize(shader, &values);
+        let compute = self.compile(entry, &specialized, params)?;
+        self.specializations.pipelines.insert(key, compute.clone());
+        Ok(compute)
+    }
+
+    /// How often `compile_specialized` reused a cached pipeline or had to compile one
+    pub fn specialization_cache_stats(&self) -> CacheStats {
+        self.specializations.stats
+    }
+
+    /// Times `entry` of `shader` on `input` once for each of `candidate_local_sizes` and returns
+    /// the fastest. The shader has to declare `layout(local_size_x_id = 0) in;` so the workgroup
+    /// size can be specialized, bounds-check its invocations, and bind only `input` at
+    /// `binding = 0` of set 0. Every candidate runs on a fresh copy of `input`.
+    ///
+    /// Candidates that fail to compile are skipped. Fails with `CallError::NoCandidates` if
+    /// that leaves none, e.g. because `candidate_local_sizes` is empty.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn autotune<T: bytemuck::Pod>(
+        &mut self,
+        shader: &Vec<u32>,
+        entry: &str,
+        input: &[T],
+        candidate_local_sizes: &[u32],
+    ) -> Result<u32, CallError> {
+        const RUNS: usize = 5;
+        let total = input.len() as u32;
+        let mut best = (std::time::Duration::MAX, None);
+        for &local_size in candidate_local_sizes {
+            let gpu = self.to_device(input);
+            let args = ParamsBuilder::new().param(Some(&gpu)).build(Some(0));
+            let constants = [(0, local_size as f64)];
+            let compute = match self.compile_specialized(entry, shader, &args.0, &constants) {
+                Ok(compute) => compute,
+                Err(_) => continue,
+            };
+            let workspace = (workgroup_count(total, local_size), 1, 1);
+
+            // The first run also pays for driver-side pipeline setup and is not counted
+            let index = self.submit_compute(&compute, workspace, &args.1, &[])?;
+            self.wait_for(index);
+            for _ in 0..RUNS {
+                let start = std::time::Instant::now();
+                let index = self.submit_compute(&compute, workspace, &args.1, &[])?;
+                self.wait_for(index);
+                let elapsed = start.elapsed();
+                if elapsed < best.0 {
+                    best = (elapsed, Some(local_size));
+                }
+            }
+        }
+        best.1.ok_or(CallError::NoCandidates)
+    }
+
+    /// Dispatches `workspace` workgroups of `gpu_compute` with the bindings in `args`. The
+    /// pipeline is only borrowed, so it can be dispatched again without recompiling.
+    pub fn call<'a>(
+        &mut self,
+        gpu_compute: &GPUCompute,
+        workspace: (u32, u32, u32),
+        args: &HashMap<u32, wgpu::BindGroupEntry<'a>>,
+    ) -> Result<wgpu::SubmissionIndex, CallError> {
+        self.submit_compute(gpu_compute, workspace, args, &[])
+    }
+
+    /// Like `call`, for shaders that use several bind group sets. `sets` maps every set of the
+    /// layout to the entries of its bind group, e.g. inputs in set 0 and outputs in set 1.
+    pub fn call_sets<'a>(
+        &mut self,
+        gpu_compute: &GPUCompute,
+        workspace: (u32, u32, u32),
+        sets: &HashMap<u32, HashMap<u32, wgpu::BindGroupEntry<'a>>>,
+    ) -> Result<wgpu::SubmissionIndex, CallError> {
+        let sets = sets.iter().map(|(set, args)| (*set, args)).collect();
+        self.record_and_submit(gpu_compute, workspace.into(), &sets, &[], &[], &[])
+    }
+
+    /// Like `call`, but captures wgpu's validation errors for the dispatch and returns them
+    /// as `CallError::Validation` instead of only logging them. Blocks until wgpu has
+    /// validated the submission, which makes it most useful in tests and debug builds.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn call_checked<'a>(
+        &mut self,
+        gpu_compute: &GPUCompute,
+        workspace: (u32, u32, u32),
+        args: &HashMap<u32, wgpu::BindGroupEntry<'a>>,
+    ) -> Result<wgpu::SubmissionIndex, CallError> {
+        self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+        let result = self.submit_compute(gpu_compute, workspace, args, &[]);
+        if let Some(error) = block_on(self.device.pop_error_scope()) {
+            return Err(CallError::Validation(error));
+        }
+        result
+    }
+
+    /// Like `call`, but re-records and resubmits the dispatch up to `max_retries` times when the
+    /// device reports running out of memory, which can be transient when other work frees
+    /// memory in the meantime. Before each retry the device waits for its outstanding work.
+    ///
+    /// Only use this with idempotent kernels: a failed attempt may have partly run, so a
+    /// kernel accumulating into its buffers would count some of its work twice. A lost device
+    /// is not recovered, as the buffers in `args` belong to it.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn call_with_retry<'a>(
+        &mut self,
+        gpu_compute: &GPUCompute,
+        workspace: (u32, u32, u32),
+        args: &HashMap<u32, wgpu::BindGroupEntry<'a>>,
+        max_retries: u32,
+    ) -> Result<wgpu::SubmissionIndex, CallError> {
+        self.retry_out_of_memory(max_retries, |device| {
+            device.submit_compute(gpu_compute, workspace, args, &[])
+        })
+    }
+
+    /// Runs `attempt` again, up to `max_retries` times, while the device runs out of memory
+    /// during it or it returns `CallError::OutOfMemory`, e.g. to retry allocating the buffers
+    /// of a dispatch together with the dispatch itself. See `call_with_retry` for when
+    /// retrying is safe.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn retry_out_of_memory<R>(
+        &mut self,
+        max_retries: u32,
+        mut attempt: impl FnMut(&mut Device) -> Result<R, CallError>,
+    ) -> Result<R, CallError> {
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            self.device.push_error_scope(wgpu::ErrorFilter::OutOfMemory);
+            let result = attempt(self);
+            let error = match (block_on(self.device.pop_error_scope()), result) {
+                (Some(error), _) | (None, Err(CallError::OutOfMemory { error, .. })) => error,
+                (None, result) => return result,
+            };
+            if attempts > max_retries {
+                return Err(CallError::OutOfMemory { attempts, error });
+            }
+            self.device.poll(wgpu::Maintain::Wait);
+        }
+    }
+
+    /// Like `call`, but measures the dispatch on the GPU with timestamp queries and waits for
+    /// it to return its duration in nanoseconds. When the adapter doesn't support
+    /// `Features::TIMESTAMP_QUERY` the dispatch is still submitted, but `None` is returned.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn call_timed<'a>(
+        &mut self,
+        gpu_compute: &GPUCompute,
+        workspace: (u32, u32, u32),
+        args: &HashMap<u32, wgpu::BindGroupEntry<'a>>,
+    ) -> Result<Option<u64>, CallError> {
+        if !self.timestamp_queries() {
+            self.submit_compute(gpu_compute, workspace, args, &[])?;
+            return Ok(None);
+        }
+        let pass = self.timed_pass(&gpu_compute.entry);
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        self.record_pass(
+            &mut encoder,
+            gpu_compute,
+            workspace.into(),
+            &single_set(args),
+            &[],
+            &[],
+            &[],
+            Some(&pass.query_set),
+        )?;
+        pass.resolve(&mut encoder);
+        self.submit_encoder(encoder, &gpu_compute.entry);
+
+        let (start, end) = match pass.read(&self.device) {
+            Some(ticks) => ticks,
+            None => return Ok(None),
+        };
+        if let Some(trace) = &self.trace {
+            trace.timed_passes.lock().unwrap().push(pass);
+        }
+        let period = self.queue.get_timestamp_period() as f64;
+        Ok(Some((end.saturating_sub(start) as f64 * period) as u64))
+    }
+
+    /// Like `call`, but sets the push constants of a kernel compiled with
+    /// `compile_with_push_constants` to `push_constants`, which must have the declared size
+    pub fn call_with_push_constants<'a>(
+        &mut self,
+        gpu_compute: &GPUCompute,
+        workspace: (u32, u32, u32),
+        args: &HashMap<u32, wgpu::BindGroupEntry<'a>>,
+        push_constants: &[u8],
+    ) -> Result<wgpu::SubmissionIndex, CallError> {
+        let (x, y, z) = workspace;
+        self.dispatch(gpu_compute)
+            .push_bytes(push_constants)
+            .groups(x, y, z)
+            .args(args)
+            .run()
+    }
+
+    /// Like `call`, but binds the dynamic bindings of set 0 at `dynamic_offsets` bytes, one
+    /// offset per binding in binding order, see `ParamsBuilder::uniform_dynamic`. `call` binds
+    /// them at offset 0.
+    pub fn call_with_dynamic_offsets<'a>(
+        &mut self,
+        gpu_compute: &GPUCompute,
+        workspace: (u32, u32, u32),
+        args: &HashMap<u32, wgpu::BindGroupEntry<'a>>,
+        dynamic_offsets: &[u32],
+    ) -> Result<wgpu::SubmissionIndex, CallError> {
+        self.record_and_submit(
+            gpu_compute,
+            workspace.into(),
+            &single_set(args),
+            &[],
+            &[],
+            dynamic_offsets,
+        )
+    }
+
+    /// Like `call`, but reads the workgroup counts on the GPU from three `u32`s at byte `offset`
+    /// of `indirect_buffer`, e.g. written by an earlier pass that counted its output. The
+    /// buffer has to be created with `to_device_indirect`, and `offset` has to be a multiple of
+    /// 4 with the three counts inside the buffer, fails with `CallError::NotIndirect` or
+    /// `CallError::IndirectOffset` otherwise.
+    pub fn call_indirect<'a>(
+        &mut self,
+        gpu_compute: &GPUCompute,
+        indirect_buffer: &GPUData<[u32]>,
+        offset: u64,
+        args: &HashMap<u32, wgpu::BindGroupEntry<'a>>,
+    ) -> Result<wgpu::SubmissionIndex, CallError> {
+        if !indirect_buffer.is_indirect() {
+            return Err(CallError::NotIndirect);
+        }
+        let buffer_size = indirect_buffer.storage_buffer.size();
+        if !offset.is_multiple_of(4) || offset + 12 > buffer_size {
+            return Err(CallError::IndirectOffset {
+                offset,
+                buffer_size,
+            });
+        }
+        self.record_and_submit(
+            gpu_compute,
+            Workgroups::Indirect(&indirect_buffer.storage_buffer, offset),
+            &single_set(args),
+            &[],
+            &[],
+            &[],
+        )
+    }
+
+    /// Dispatches enough workgroups to cover `total_threads` invocations along each
+    /// dimension, using the `local_size` reflected from the shader. When `total_threads`
+    /// isn't a multiple of `local_size` the last workgroups run some extra invocations,
+    /// which the shader has to bounds-check. Fails with `CallError::UnknownLocalSize` if the
+    /// `local_size` could not be reflected.
+    pub fn dispatch_threads<'a>(
+        &mut self,
+        gpu_compute: &GPUCompute,
+        total_threads: (u32, u32, u32),
+        args: &HashMap<u32, wgpu::BindGroupEntry<'a>>,
+    ) -> Result<wgpu::SubmissionIndex, CallError> {
+        let local = gpu_compute.local_size.ok_or(CallError::UnknownLocalSize)?;
+        self.dispatch_grid(gpu_compute, total_threads, local, args)
+    }
+
+    /// Dispatches one invocation per cell of a grid of `dims` cells, in workgroups of `local`
+    /// invocations, so `gl_GlobalInvocationID.xyz` are the grid coordinates. Each axis is
+    /// rounded up to whole workgroups, so the shader has to bounds-check against `dims`.
+    ///
+    /// `local` has to be the workgroup size the shader declares, fails with
+    /// `CallError::LocalSizeMismatch` if it differs from the reflected `local_size`.
+    pub fn dispatch_grid<'a>(
+        &mut self,
+        gpu_compute: &GPUCompute,
+        dims: (u32, u32, u32),
+        local: (u32, u32, u32),
+        args: &HashMap<u32, wgpu::BindGroupEntry<'a>>,
+    ) -> Result<wgpu::SubmissionIndex, CallError> {
+        check_local_size(gpu_compute, local)?;
+        let max = self.device.limits().max_compute_workgroups_per_dimension;
+        let workspace = (
+            workgroup_count(dims.0, local.0),
+            workgroup_count(dims.1, local.1),
+            workgroup_count(dims.2, local.2),
+        );
+        let groups = [workspace.0, workspace.1, workspace.2];
+        if let Some(axis) = groups.iter().position(|g| *g > max) {
+            return Err(CallError::TooManyWorkgroups {
+                axis: axis as u32,
+                groups: groups[axis],
+                max,
+            });
+        }
+        self.submit_compute(gpu_compute, workspace, args, &[])
+    }
+
+    /// Dispatches `ceil(n / local_size_x)` workgroups, one invocation per element of a buffer
+    /// of `n` elements, for shaders declaring `layout(local_size_x = ...)`. Passing
+    /// `(n, 1, 1)` to `call` instead would run `local_size_x` times too many invocations.
+    /// The last workgroup may run past the end, so the shader has to return early when
+    /// `gl_GlobalInvocationID.x >= n`.
+    pub fn dispatch_1d<'a>(
+        &mut self,
+        gpu_compute: &GPUCompute,
+        n: u32,
+        local_size_x: u32,
+        args: &HashMap<u32, wgpu::BindGroupEntry<'a>>,
+    ) -> Result<wgpu::SubmissionIndex, CallError> {
+        self.dispatch_grid(gpu_compute, (n, 1, 1), (local_size_x, 1, 1), args)
+    }
+
+    /// Like `dispatch_1d` for a `(width, height)` image or matrix in workgroups of
+    /// `(local_size_x, local_size_y)`. The shader has to bounds-check both coordinates of
+    /// `gl_GlobalInvocationID.xy`. Volumes are dispatched with `dispatch_grid`.
+    pub fn dispatch_2d<'a>(
+        &mut self,
+        gpu_compute: &GPUCompute,
+        size: (u32, u32),
+        local: (u32, u32),
+        args: &HashMap<u32, wgpu::BindGroupEntry<'a>>,
+    ) -> Result<wgpu::SubmissionIndex, CallError> {
+        self.dispatch_grid(
+            gpu_compute,
+            (size.0, size.1, 1),
+            (local.0, local.1, 1),
+            args,
+        )
+    }
+
+    /// Runs `total` invocations, which may be more than fit in `u32`, as a sequence of tiles
+    /// of at most `max_compute_workgroups_per_dimension` workgroups of `local_size` each,
+    /// recorded into a single submission.
+    ///
+    /// Each tile gets its 64-bit base offset and its number of invocations as push constants,
+    /// so the pipeline has to be compiled with 12 bytes of push constants laid out as
+    /// ```ignore
+    ///     layout(push_constant) uniform Tile { uvec2 base; uint count; };
+    ///     // index = base + gl_GlobalInvocationID.x, if gl_GlobalInvocationID.x < count
+    /// ```
+    pub fn dispatch_u64<'a>(
+        &mut self,
+        gpu_compute: &GPUCompute,
+        total: u64,
+        local_size: u32,
+        args: &HashMap<u32, wgpu::BindGroupEntry<'a>>,
+    ) -> Result<wgpu::SubmissionIndex, CallError> {
+        if gpu_compute.push_constant_size != 12 {
+            return Err(CallError::PushConstantSize {
+                expected: gpu_compute.push_constant_size,
+                found: 12,
+            });
+        }
+        check_local_size(gpu_compute, (local_size, 1, 1))?;
+        let max = self.device.limits().max_compute_workgroups_per_dimension;
+        let per_tile = local_size as u64 * max as u64;
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        let mut base = 0u64;
+        while base < total {
+            let count = (total - base).min(per_tile) as u32;
+            let push_constants = [base as u32, (base >> 32) as u32, count];
+            let workspace = (workgroup_count(count, local_size), 1, 1);
+            self.record_compute(
+                &mut encoder,
+                gpu_compute,
+                workspace.into(),
+                &single_set(args),
+                &[],
+                &push_constants,
+                &[],
+            )?;
+            base += count as u64;
+        }
+
+        Ok(self.submit_encoder(encoder, &gpu_compute.entry))
+    }
+
+    /// Lays `groups` workgroups out in rows of at most `max_compute_workgroups_per_dimension`,
+    /// for the bundled kernels that continue each row along y where the previous one ended,
+    /// i.e. index with `id.y * num_workgroups.x * local_size_x + id.x`
+    pub(crate) fn workgroup_rows(&self, groups: u32) -> Result<(u32, u32, u32), CallError> {
+        let max = self.device.limits().max_compute_workgroups_per_dimension;
+        let rows = workgroup_count(groups, max);
+        if rows > max {
+            return Err(CallError::TooManyWorkgroups {
+                axis: 1,
+                groups: rows,
+                max,
+            });
+        }
+        Ok(if rows > 1 {
+            (max, rows, 1)
+        } else {
+            (groups, 1, 1)
+        })
+    }
+
+    /// Creates the bind group of `set` for `gpu_compute` once, so it can be passed to
+    /// `call_cached` for many dispatches instead of being rebuilt on every call. Identical
+    /// layouts are shared by wgpu, so the group can also be used with other kernels
+    /// declaring the same bindings for that set.
+    pub fn create_bind_group<'a>(
+        &self,
+        gpu_compute: &GPUCompute,
+        set: u32,
+        entries: &HashMap<u32, wgpu::BindGroupEntry<'a>>,
+    ) -> CachedBindGroup {
+        let label =
+            self.resource_label(&format!("{} Cached Bind Group {}", gpu_compute.entry, set));
+        CachedBindGroup {
+            set,
+            bind_group: self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some(&label),
+                layout: &gpu_compute.bind_group_layouts[&set],
+                entries: sorted_by_binding(entries.values().cloned().collect(), |entry| {
+                    entry.binding
+                })
+                .as_slice(),
+            }),
+        }
+    }
+
+    /// Like `call`, but binds the precreated `cached` groups for their sets and only
+    /// builds a bind group from `args` for the remaining set
+    pub fn call_cached<'a>(
+        &mut self,
+        gpu_compute: &GPUCompute,
+        workspace: (u32, u32, u32),
+        args: &HashMap<u32, wgpu::BindGroupEntry<'a>>,
+        cached: &[&CachedBindGroup],
+    ) -> Result<wgpu::SubmissionIndex, CallError> {
+        self.submit_compute(gpu_compute, workspace, args, cached)
+    }
+
+    pub(crate) fn submit_compute<'a>(
+        &mut self,
+        gpu_compute: &GPUCompute,
+        workspace: (u32, u32, u32),
+        args: &HashMap<u32, wgpu::BindGroupEntry<'a>>,
+        cached: &[&CachedBindGroup],
+    ) -> Result<wgpu::SubmissionIndex, CallError> {
+        self.record_and_submit(
+            gpu_compute,
+            workspace.into(),
+            &single_set(args),
+            cached,
+            &[],
+            &[],
+        )
+    }
+
+    pub(crate) fn record_and_submit<'a>(
+        &mut self,
+        gpu_compute: &GPUCompute,
+        workspace: Workgroups,
+        sets: &HashMap<u32, &HashMap<u32, wgpu::BindGroupEntry<'a>>>,
+        cached: &[&CachedBindGroup],
+        push_constants: &[u32],
+        dynamic_offsets: &[u32],
+    ) -> Result<wgpu::SubmissionIndex, CallError> {
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        self.record_compute(
+            &mut encoder,
+            gpu_compute,
+            workspace,
+            sets,
+            cached,
+            push_constants,
+            dynamic_offsets,
+        )?;
+
+        Ok(self.submit_encoder(encoder, &gpu_compute.entry))
+    }
+
+    /// Submits the dispatches recorded in `encoder`, throttled by `set_max_inflight` and
+    /// traced under `entry` when tracing is on
+    pub(crate) fn submit_encoder(
+        &mut self,
+        encoder: wgpu::CommandEncoder,
+        entry: &str,
+    ) -> wgpu::SubmissionIndex {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.throttle();
+        #[cfg(not(target_arch = "wasm32"))]
+        let started = std::time::Instant::now();
+        let index = self.queue.submit(Some(encoder.finish()));
+
+        #[cfg(not(target_arch = "wasm32"))]
+        self.track_inflight(&index);
+        #[cfg(not(target_arch = "wasm32"))]
+        self.trace_dispatch(entry, started, &index);
+        index
+    }
+
+    /// Records the compute pass of a dispatch into `encoder` without submitting it, with a bind
+    /// group built from `sets` for every set that isn't `cached`. `dynamic_offsets` apply to
+    /// the dynamic bindings of set 0.
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn record_compute<'a>(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        gpu_compute: &GPUCompute,
+        workspace: Workgroups,
+        sets: &HashMap<u32, &HashMap<u32, wgpu::BindGroupEntry<'a>>>,
+        cached: &[&CachedBindGroup],
+        push_constants: &[u32],
+        dynamic_offsets: &[u32],
+    ) -> Result<(), CallError> {
+        self.record_pass(
+            encoder,
+            gpu_compute,
+            workspace,
+            sets,
+            cached,
+            push_constants,
+            dynamic_offsets,
+            None,
+        )
+    }
+
+    /// Like `record_compute`, but writes the timestamps of the start and end of the pass to
+    /// the queries 0 and 1 of `timestamps`. Without `timestamps` the pass gets queries of its
+    /// own while tracing with timestamp queries, see `set_tracing`.
+    #[allow(clippy::too_many_arguments)]
+    fn record_pass<'a>(
+        &self,
+        encoder: &mut wgpu::CommandEncoder,
+        gpu_compute: &GPUCompute,
+        workspace: Workgroups,
+        sets: &HashMap<u32, &HashMap<u32, wgpu::BindGroupEntry<'a>>>,
+        cached: &[&CachedBindGroup],
+        push_constants: &[u32],
+        dynamic_offsets: &[u32],
+        timestamps: Option<&wgpu::QuerySet>,
+    ) -> Result<(), CallError> {
+        let mut dynamic_bindings: Vec<u32> = gpu_compute
+            .layouts
+            .get(&0)
+            .into_iter()
+            .flat_map(|bindings| bindings.values())
+            .filter(|(layout, _)| {
+                matches!(
+                    layout.ty,
+                    wgpu::BindingType::Buffer {
+                        has_dynamic_offset: true,
+                        ..
+                    }
+                )
+            })
+            .map(|(layout, _)| layout.binding)
+            .collect();
+        dynamic_bindings.sort();
+        // Without any offsets, e.g. from `call`, every dynamic binding starts at its first element
+        let zeros = vec![0; dynamic_bindings.len()];
+        let dynamic_offsets = if dynamic_offsets.is_empty() {
+            &zeros[..]
+        } else {
+            dynamic_offsets
+        };
+        if dynamic_offsets.len() != dynamic_bindings.len() {
+            return Err(CallError::DynamicOffsetCount {
+                expected: dynamic_bindings.len() as u32,
+                found: dynamic_offsets.len() as u32,
+            });
+        }
+        let alignment = self.device.limits().min_uniform_buffer_offset_alignment;
+        if let Some(offset) = dynamic_offsets.iter().find(|o| *o % alignment != 0) {
+            return Err(CallError::DynamicOffsetAlignment {
+                offset: *offset,
+                alignment,
+            });
+        }
+        for (binding, offset) in dynamic_bindings.iter().zip(dynamic_offsets) {
+            let entry = sets.get(&0).and_then(|args| args.get(binding));
+            if let Some(wgpu::BindGroupEntry {
+                resource: wgpu::BindingResource::Buffer(buffer),
+                ..
+            }) = entry
+            {
+                let buffer_size = buffer.buffer.size();
+                let size = buffer
+                    .size
+                    .map_or(buffer_size - buffer.offset, |size| size.get());
+                if buffer.offset + *offset as u64 + size > buffer_size {
+                    return Err(CallError::DynamicOffsetOutOfBounds {
+                        binding: *binding,
+                        offset: *offset,
+                        buffer_size,
+                    });
+                }
+            }
+        }
+        let set_is_cached = |set: u32| cached.iter().any(|group| group.set == set);
+
+        // Every binding declared by the layout of a set built from `sets` needs an entry
+        for (set, bindings) in &gpu_compute.layouts {
+            if set_is_cached(*set) {
+                continue;
+            }
+            let mut declared: Vec<&u32> = bindings.keys().collect();
+            declared.sort();
+            for binding in declared {
+                if !sets.get(set).is_some_and(|args| args.contains_key(binding)) {
+                    return Err(CallError::MissingBinding {
+                        set: *set,
+                        binding: *binding,
+                    });
+                }
+            }
+        }
+
+        let mut bind_groups = HashMap::new();
+        for (set_num, layout) in gpu_compute.bind_group_layouts.iter() {
+            if set_is_cached(*set_num) {
+                continue;
+            }
+            let bind_group_label =
+                self.resource_label(&format!("{} Bind Group {}", gpu_compute.entry, set_num));
+            let entries = match sets.get(set_num) {
+                Some(args) => {
+                    sorted_by_binding(args.values().cloned().collect(), |entry| entry.binding)
+                }
+                None => vec![],
+            };
+            bind_groups.insert(
+                *set_num,
+                self.device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: Some(&bind_group_label),
+                    layout,
+                    entries: entries.as_slice(),
+                }),
+            );
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        let traced = match (&self.trace, timestamps) {
+            (Some(_), None) if self.timestamp_queries() => {
+                Some(self.timed_pass(&gpu_compute.entry))
+            }
+            _ => None,
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        let timestamps = timestamps.or_else(|| traced.as_ref().map(|pass| &pass.query_set));
+        let timestamp_writes = timestamps.map(|query_set| wgpu::ComputePassTimestampWrites {
+            query_set,
+            beginning_of_pass_write_index: Some(0),
+            end_of_pass_write_index: Some(1),
+        });
+        {
+            let mut cpass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: None,
+                timestamp_writes,
+            });
+            cpass.set_pipeline(&gpu_compute.compute_pipeline);
+
+            for set_num in gpu_compute.bind_group_layouts.keys() {
+                // bind_group = collection of bindings
+                let offsets: &[u32] = if *set_num == 0 { dynamic_offsets } else { &[] };
+                match cached.iter().find(|group| group.set == *set_num) {
+                    Some(group) => cpass.set_bind_group(*set_num, &group.bind_group, offsets),
+                    None => cpass.set_bind_group(*set_num, &bind_groups[set_num], offsets),
+                }
+            }
+            if !push_constants.is_empty() {
+                cpass.set_push_constants(0, bytemuck::cast_slice(push_constants));
+            }
+            match workspace {
+                Workgroups::Direct(x, y, z) => cpass.dispatch_workgroups(x, y, z),
+                Workgroups::Indirect(buffer, offset) => {
+                    cpass.dispatch_workgroups_indirect(buffer, offset)
+                }
+            }
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        if let (Some(pass), Some(trace)) = (traced, &self.trace) {
+            pass.resolve(encoder);
+            trace.timed_passes.lock().unwrap().push(pass);
+        }
+        Ok(())
+    }
+
+    /// Runs `entry` of `shader` on a copy of `input` and returns the result, recording the
+    /// upload, the dispatch and the readback into a single submission. This saves the
+    /// submissions and the waits of separate `to_device`, `call` and `get` for one-shot work.
+    ///
+    /// `input` is bound at `binding = 0` of set 0, the only binding the kernel may declare.
+    /// A failed readback is returned as `CallError::Readback`.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn run_fused<T: bytemuck::Pod>(
+        &mut self,
+        shader: &[u32],
+        entry: &str,
+        input: &[T],
+        workspace: (u32, u32, u32),
+    ) -> Result<Box<[T]>, CallError> {
+        let gpu = self.create_data(input, STORAGE_USAGE, None);
+        let args = ParamsBuilder::new().param(Some(&gpu)).build(Some(0));
+        let compute = self
+            .compile(entry, shader, &args.0)
+            .map_err(CallError::Compile)?;
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_buffer_to_buffer(
+            &gpu.staging_buffer,
+            0,
+            &gpu.storage_buffer,
+            0,
+            gpu.padded_size(),
+        );
+        self.record_compute(
+            &mut encoder,
+            &compute,
+            workspace.into(),
+            &single_set(&args.1),
+            &[],
+            &[],
+            &[],
+        )?;
+        encoder.copy_buffer_to_buffer(
+            &gpu.storage_buffer,
+            0,
+            &gpu.staging_buffer,
+            0,
+            gpu.padded_size(),
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let buffer_future = map_read(gpu.staging_buffer.slice(0..gpu.padded_size()));
+        self.device.poll(wgpu::Maintain::Wait);
+        if let Err(error) = block_on(buffer_future) {
+            return Err(self.map_error(error).into());
+        }
+        Ok(read_staging(&gpu))
+    }
+
+    /// Blocks until the work of the submission `index`, as returned by `call`, has completed.
+    /// Unlike `get`, later submissions don't have to finish as well.
+    pub fn wait_for(&self, index: wgpu::SubmissionIndex) {
+        self.device
+            .poll(wgpu::Maintain::WaitForSubmissionIndex(index));
+    }
+
+    /// Drives the device forward, completing finished work and firing the callbacks of mapped
+    /// buffers. With `wait` it blocks until all submitted work is done, otherwise it only
+    /// processes what has already completed, e.g. between attempts of a non-blocking `get`.
+    pub fn poll(&self, wait: bool) {
+        self.device.poll(if wait {
+            wgpu::Maintain::Wait
+        } else {
+            wgpu::Maintain::Poll
+        });
+    }
+
+    /// Starts a thread that drives the device while maps of `readback_async` are pending, so
+    /// their futures resolve without the awaiting thread driving the device itself. The thread
+    /// sleeps while no map is pending and blocks on the GPU while one is, so it doesn't keep a
+    /// core busy. It runs until the device is dropped.
+    ///
+    /// Does nothing if it is already running, or if the device was created with
+    /// `from_instance`, whose instance the thread would have to poll.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn spawn_poll_thread(&mut self) {
+        let instance = match (&self.poll_thread, &self.instance) {
+            (None, Some(instance)) => instance.clone(),
+            _ => return,
+        };
+        let pending_maps = self.pending_maps.clone();
+        pending_maps.set_stop(false);
+        let handle = std::thread::spawn(move || {
+            while pending_maps.wait() {
+                instance.poll_all(true);
+            }
+        });
+        self.poll_thread = Some(handle);
+    }
+
+    /// Signals the thread of `spawn_poll_thread` to exit and joins it
+    #[cfg(not(target_arch = "wasm32"))]
+    fn stop_poll_thread(&mut self) {
+        if let Some(handle) = self.poll_thread.take() {
+            self.pending_maps.set_stop(true);
+            let _ = handle.join();
+        }
+    }
+}
+
+/// Waits for all submitted work before the device and queue are released, so buffers and
+/// pipelines are never freed while the GPU still uses them. Dropping a `Device` with
+/// outstanding work therefore blocks until that work has finished.
+impl Drop for Device {
+    fn drop(&mut self) {
+        #[cfg(not(target_arch = "wasm32"))]
+        self.stop_poll_thread();
+        // A panic inside the wait would abort the process while already unwinding
+        if !std::thread::panicking() {
+            self.device.poll(wgpu::Maintain::Wait);
+        }
+    }
+}
+
+/// An instance enumerating the adapters of `backends`
+fn create_instance(backends: wgpu::Backends) -> wgpu::Instance {
+    wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends,
+        ..Default::default()
+    })
+}
+
+/// The adapter at `device_index` of the adapters enumerated by `instance`
+#[cfg(not(target_arch = "wasm32"))]
+fn select_adapter(
+    instance: &wgpu::Instance,
+    device_index: usize,
+) -> Result<wgpu::Adapter, DeviceError> {
+    let mut adapters = instance.enumerate_adapters(wgpu::Backends::PRIMARY);
+    if adapters.is_empty() {
+        return Err(DeviceError::NoBackends);
+    }
+    if device_index >= adapters.len() {
+        return Err(DeviceError::AdapterNotFound(device_index));
+    }
+    Ok(adapters.swap_remove(device_index))
+}
+
+/// Reflects the `workgroup_size` of the WGSL compute entry point `entry` with naga, the
+/// front end wgpu itself compiles the source with
+fn wgsl_workgroup_size(source: &str, entry: &str) -> Result<Option<(u32, u32, u32)>, CompileError> {
+    let module = wgpu::naga::front::wgsl::parse_str(source)
+        .map_err(|error| CompileError::InvalidWgsl(error.emit_to_string(source)))?;
+    Ok(module
+        .entry_points
+        .iter()
+        .find(|point| point.name == entry && point.stage == wgpu::naga::ShaderStage::Compute)
+        .map(|point| {
+            let [x, y, z] = point.workgroup_size;
+            (x, y, z)
+        }))
+}
+
+/// `bytes` as the words `set_push_constants` takes, checked against the size declared by
+/// `gpu_compute`
+pub(crate) fn push_constant_words(
+    gpu_compute: &GPUCompute,
+    bytes: &[u8],
+) -> Result<Vec<u32>, CallError> {
+    let expected = gpu_compute.push_constant_size();
+    let found = bytes.len() as u32;
+    if expected != found || !found.is_multiple_of(4) {
+        return Err(CallError::PushConstantSize { expected, found });
+    }
+    let mut words = vec![0u32; bytes.len() / 4];
+    bytemuck::cast_slice_mut::<u32, u8>(&mut words).copy_from_slice(bytes);
+    Ok(words)
+}
+
+/// How many workgroups a recorded dispatch runs
+pub(crate) enum Workgroups<'b> {
+    /// Counts along x, y and z known on the host
+    Direct(u32, u32, u32),
+    /// Counts read by the GPU from a buffer at a byte offset
+    Indirect(&'b wgpu::Buffer, u64),
+}
+
+impl From<(u32, u32, u32)> for Workgroups<'_> {
+    fn from((x, y, z): (u32, u32, u32)) -> Self {
+        Workgroups::Direct(x, y, z)
+    }
+}
+
+/// The arguments of a call as the only set, set 0
+pub(crate) fn single_set<'s, 'a>(
+    args: &'s HashMap<u32, wgpu::BindGroupEntry<'a>>,
+) -> HashMap<u32, &'s HashMap<u32, wgpu::BindGroupEntry<'a>>> {
+    let mut sets = HashMap::new();
+    sets.insert(0, args);
+    sets
+}
+
+/// Checks the workgroup size passed to a dispatch helper against the one reflected from the
+/// shader, if known
+fn check_local_size(gpu_compute: &GPUCompute, local: (u32, u32, u32)) -> Result<(), CallError> {
+    if local.0 == 0 || local.1 == 0 || local.2 == 0 {
+        return Err(CallError::ZeroLocalSize);
+    }
+    match gpu_compute.local_size {
+        Some(expected) if expected != local => Err(CallError::LocalSizeMismatch {
+            expected,
+            found: local,
+        }),
+        _ => Ok(()),
+    }
+}
+
+/// Number of workgroups of `local` invocations needed to cover `total` invocations
+pub(crate) fn workgroup_count(total: u32, local: u32) -> u32 {
+    total.div_ceil(local)
+}
+
+/// Orders layout or bind group entries by binding number, since bindings placed with
+/// `ParamsBuilder::param_at` need not be contiguous
+fn sorted_by_binding<E>(mut entries: Vec<E>, binding: impl Fn(&E) -> u32) -> Vec<E> {
+    entries.sort_by_key(binding);
+    entries
+}
+
+fn hash_of<H: std::hash::Hash + ?Sized>(value: &H) -> u64 {
+    use std::hash::Hasher;
+    let mut hasher = std::collections::hash_map::DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// Hashes the binding layouts of every set, independent of the order of the maps
+fn layout_hash(params: &GPUSetGroupLayout) -> u64 {
+    let mut entries: Vec<(u32, &wgpu::BindGroupLayoutEntry)> = params
+        .set_bind_group_layouts
+        .iter()
+        .flat_map(|(set, bindings)| bindings.values().map(move |b| (*set, &b.0)))
+        .collect();
+    entries.sort_by_key(|(set, entry)| (*set, entry.binding));
+    hash_of(&entries)
+}
+
+/// A flag set once `device` is lost, e.g. after a driver reset
+fn watch_device_lost(device: &wgpu::Device) -> Arc<AtomicBool> {
+    let lost = Arc::new(AtomicBool::new(false));
+    let flag = lost.clone();
+    device.set_device_lost_callback(move |_reason, _message| flag.store(true, Ordering::SeqCst));
+    lost
+}
+
+/// Rounds `value` up to the next multiple of `alignment`
+pub(crate) fn align_to(value: u64, alignment: u64) -> u64 {
+    value.div_ceil(alignment) * alignment
+}
+
+///
+/// Reads back a buffer of `#[repr(C)]` structs and splits the named fields into one `Vec`
+/// each, returned as a tuple in the order given. Expands to a future like `Device::get`.
+/// ```ignore
+///     let particles = device.to_device(particles.as_slice());
+///     let (pos, vel, mass) = futures::executor::block_on(alkomp::get_fields!(
+///         device, &particles, Particle { pos, vel, mass }
+///     ))
+///     .unwrap();
+/// ```
+///
+#[macro_export]
+macro_rules! get_fields {
+    ($device:expr, $gpu:expr, $ty:path { $($field:ident),+ $(,)? }) => {
+        async {
+            let data = $device.get($gpu).await?;
+            Ok::<_, $crate::GetError>(($(
+                data.iter()
+                    .map(|s: &$ty| s.$field)
+                    .collect::<Vec<_>>(),
+            )+))
+        }
+    };
+}
+
+/// Requests a read mapping of `slice`. The future resolves once the device has been polled
+/// after the map completed.
+pub(crate) fn map_read(
+    slice: wgpu::BufferSlice<'_>,
+) -> impl std::future::Future<Output = Result<(), wgpu::BufferAsyncError>> {
+    map_read_then(slice, || {})
+}
+
+/// Like `map_read`, calling `done` from the map callback
+fn map_read_then<F>(
+    slice: wgpu::BufferSlice<'_>,
+    done: F,
+) -> impl std::future::Future<Output = Result<(), wgpu::BufferAsyncError>>
+where
+    F: FnOnce() + Send + 'static,
+{
+    let (sender, receiver) = futures::channel::oneshot::channel();
+    slice.map_async(wgpu::MapMode::Read, move |result| {
+        done();
+        // The caller may have stopped waiting for the map
+        let _ = sender.send(result);
+    });
+    receiver.map(|result| result.unwrap_or(Err(wgpu::BufferAsyncError)))
+}
+
+/// A buffer whose map was requested by `Device::readback_async` or `get_cancellable`, unmapped
+/// on drop unless the map has completed, so dropping the future or cancelling abandons the
+/// map. While armed it flags its `GPUData`, which then keeps the buffer out of the pool.
+struct PendingMap<'b>(Option<(&'b wgpu::Buffer, &'b AtomicBool)>);
+
+impl<'b> PendingMap<'b> {
+    fn arm(buffer: &'b wgpu::Buffer, map_pending: &'b AtomicBool) -> Self {
+        map_pending.store(true, Ordering::SeqCst);
+        PendingMap(Some((buffer, map_pending)))
+    }
+
+    /// Keeps the buffer mapped, once the map has completed or failed
+    fn disarm(mut self) {
+        if let Some((_, map_pending)) = self.0.take() {
+            map_pending.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+impl Drop for PendingMap<'_> {
+    fn drop(&mut self) {
+        if let Some((buffer, map_pending)) = self.0 {
+            buffer.unmap();
+            map_pending.store(false, Ordering::SeqCst);
+        }
+    }
+}
+
+/// Reads the mapped staging buffer of `gpu` and unmaps it afterwards.
+fn read_staging<T: bytemuck::Pod>(gpu: &GPUData<[T]>) -> Box<[T]> {
+    read_mapped(&gpu.staging_buffer, gpu)
+}
+
+/// Reads the data of `gpu` from `buffer`, one of its mapped buffers, and unmaps it afterwards.
+fn read_mapped<T: bytemuck::Pod>(buffer: &wgpu::Buffer, gpu: &GPUData<[T]>) -> Box<[T]> {
+    let data = buffer.slice(0..gpu.padded_size()).get_mapped_range();
+    // The staging buffer may be larger than the data, wgpu pads buffers created with contents
+    let result = data[..gpu.size as usize]
+        .chunks_exact(std::mem::size_of::<T>())
+        .map(|b| *bytemuck::from_bytes::<T>(b))
+        .collect();
+    drop(data);
+    buffer.unmap();
+    result
+}
+
+///
+/// A flag shared between threads to abandon a wait, e.g. from a UI cancel button.
+/// Clones share the same flag.
+///
+#[derive(Clone, Default, Debug)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::SeqCst);
+    }
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// A SPIR-V shader module created with `Device::load_module`, shared by the pipelines of its
+/// entry points
+pub struct ShaderModule {
+    module: wgpu::ShaderModule,
+    spirv: Vec<u32>,
+}
 
-    let (_, words, _) = unsafe { bytes.align_to::<u32>() };
-    assert_eq!(
-        words[0], MAGIC_NUMBER,
-        "wrong magic word {:x}. Make sure you are using a binary SPIRV file.",
-        words[0]
-    );
-    Vec::from(words)
+/// A compiled pipeline. Clones share the same pipeline and layouts.
+#[derive(Clone)]
+pub struct GPUCompute {
+    pub(crate) entry: String,
+    layouts: HashMap<u32, HashMap<u32, (wgpu::BindGroupLayoutEntry, String)>>,
+    bind_group_layouts: Arc<HashMap<u32, wgpu::BindGroupLayout>>,
+    compute_pipeline: Arc<wgpu::ComputePipeline>,
+    local_size: Option<(u32, u32, u32)>,
+    push_constant_size: u32,
+    /// The `(set, binding)` of the buffers the shader declares `readonly`, if reflected
+    readonly: Option<Vec<(u32, u32)>>,
 }
 
+/// Dispatch timings recorded while tracing is enabled with `Device::set_tracing`
 #[cfg(not(target_arch = "wasm32"))]
-pub struct Device {
-    pub device: wgpu::Device,
-    pub queue: wgpu::Queue,
-    pub info: Option<DeviceInfo>,
-}
-#[cfg(target_arch = "wasm32")]
-pub struct Device {
-    pub device: wgpu::Device,
-    pub queue: wgpu::Queue,
+struct Trace {
+    start: std::time::Instant,
+    /// Dispatches timed by the wall clock, without timestamp queries
+    events: Vec<TraceEvent>,
+    /// Passes timed by timestamp queries, recorded behind a shared reference
+    timed_passes: Mutex<Vec<TimedPass>>,
 }
 
-pub struct GPUData<T: ?Sized> {
-    pub staging_buffer: wgpu::Buffer,
-    pub storage_buffer: wgpu::Buffer,
-    pub size: u64,
-    pub phantom: PhantomData<T>,
-}
+/// Size of the two timestamps of a `TimedPass`
+#[cfg(not(target_arch = "wasm32"))]
+const TIMESTAMPS_SIZE: u64 = 2 * std::mem::size_of::<u64>() as u64;
 
-impl Device {
-    #[cfg(not(target_arch = "wasm32"))]
-    pub fn new(device_index: usize) -> Self {
-        let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
-        let mut adapter = instance.enumerate_adapters(wgpu::BackendBit::PRIMARY);
-        let adapter = adapter.nth(device_index).unwrap();
-        let (device, queue) = block_on(adapter.request_device(
-            &wgpu::DeviceDescriptor {
-                features: wgpu::Features::MAPPABLE_PRIMARY_BUFFERS,
-                limits: wgpu::Limits::default(),
-                shader_validation: false,
-            },
-            None,
-        ))
-        .unwrap();
-        let info = adapter.get_info().clone();
-        let info = DeviceInfo { info };
+/// Timestamp queries of the start and end of one pass, see `Device::call_timed`
+#[cfg(not(target_arch = "wasm32"))]
+struct TimedPass {
+    name: String,
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback_buffer: wgpu::Buffer,
+}
 
-        Device {
-            device,
-            queue,
-            info: Some(info),
-        }
+#[cfg(not(target_arch = "wasm32"))]
+impl TimedPass {
+    /// Records copying the timestamps to the readback buffer, after the pass
+    fn resolve(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.resolve_query_set(&self.query_set, 0..2, &self.resolve_buffer, 0);
+        encoder.copy_buffer_to_buffer(
+            &self.resolve_buffer,
+            0,
+            &self.readback_buffer,
+            0,
+            TIMESTAMPS_SIZE,
+        );
     }
 
-    #[cfg(target_arch = "wasm32")]
-    pub async fn new() -> Self {
-        let instance = wgpu::Instance::new(wgpu::BackendBit::PRIMARY);
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions::default())
-            .await
-            .unwrap();
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    // features: wgpu::Features::MAPPABLE_PRIMARY_BUFFERS,
-                    features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::default(),
-                    shader_validation: false,
-                },
-                None,
-            )
-            .await
-            .unwrap();
-
-        Device { device, queue }
+    /// Waits for the pass and returns its start and end timestamps in ticks
+    fn read(&self, device: &wgpu::Device) -> Option<(u64, u64)> {
+        let slice = self.readback_buffer.slice(..);
+        let buffer_future = map_read(slice);
+        device.poll(wgpu::Maintain::Wait);
+        block_on(buffer_future).ok()?;
+        let ticks = {
+            let data = slice.get_mapped_range();
+            let timestamps: &[u64] = bytemuck::cast_slice(&data);
+            (timestamps[0], timestamps[1])
+        };
+        self.readback_buffer.unmap();
+        Some(ticks)
     }
+}
 
-    pub fn to_device<T: bytemuck::Pod>(&mut self, data: &[T]) -> GPUData<[T]> {
-        let bytes = bytemuck::cast_slice(data);
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Clone)]
+struct TraceEvent {
+    name: String,
+    start_us: f64,
+    duration_us: f64,
+}
 
-        // On native we can share memory between CPU and GPU... but not in web
-        let staging_buffer = if cfg!(not(target_arch = "wasm32")) {
-            self.device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Staging Buffer"),
-                    contents: &bytes,
-                    usage: wgpu::BufferUsage::MAP_READ
-                        | wgpu::BufferUsage::COPY_DST
-                        | wgpu::BufferUsage::COPY_SRC,
-                })
-        } else {
-            self.device.create_buffer(&wgpu::BufferDescriptor {
-                label: None,
-                size: bytes.len() as u64,
-                usage: wgpu::BufferUsage::MAP_READ | wgpu::BufferUsage::COPY_DST,
-                mapped_at_creation: false,
-            })
-        };
+/// Pipelines compiled by `Device::compile_specialized`
+#[derive(Default)]
+struct SpecializationCache {
+    pipelines: HashMap<SpecializationKey, GPUCompute>,
+    stats: CacheStats,
+}
 
-        let storage_buffer = if cfg!(not(target_arch = "wasm32")) {
-            self.device.create_buffer(&wgpu::BufferDescriptor {
-                label: None,
-                size: bytes.len() as u64,
-                usage: wgpu::BufferUsage::STORAGE
-                    | wgpu::BufferUsage::COPY_DST
-                    | wgpu::BufferUsage::COPY_SRC,
-                mapped_at_creation: false,
-            })
-        } else {
-            // Preload the buffer with data if we are on web
-            self.device
-                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                    label: Some("Storage Buffer"),
-                    contents: &bytes,
-                    usage: wgpu::BufferUsage::STORAGE
-                        | wgpu::BufferUsage::COPY_DST
-                        | wgpu::BufferUsage::COPY_SRC,
-                })
-        };
+#[derive(PartialEq, Eq, Hash)]
+struct SpecializationKey {
+    shader: u64,
+    layout: u64,
+    entry: String,
+    /// Values as the bits of their `f64`, which isn't `Hash`
+    constants: Vec<(u32, u64)>,
+}
 
-        #[cfg(not(target_arch = "wasm32"))]
-        {
-            let mut encoder = self
-                .device
-                .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-            encoder.copy_buffer_to_buffer(
-                &staging_buffer,
-                0,
-                &storage_buffer,
-                0,
-                bytes.len() as u64,
-            );
+/// Hit and miss counts of the specialization cache
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub hits: u64,
+    pub misses: u64,
+}
 
-            self.queue.submit(Some(encoder.finish()));
-        }
+/// Dispatches submitted while a limit of `Device::set_max_inflight` was set, retired in
+/// submission order as their `on_submitted_work_done` callbacks fire
+#[cfg(not(target_arch = "wasm32"))]
+#[derive(Default)]
+struct Inflight {
+    max: Option<usize>,
+    submissions: VecDeque<wgpu::SubmissionIndex>,
+    submitted: u64,
+    completed: Arc<AtomicU64>,
+}
 
-        GPUData {
-            staging_buffer,
-            storage_buffer,
-            size: bytes.len() as u64,
-            phantom: PhantomData,
+#[cfg(not(target_arch = "wasm32"))]
+impl Inflight {
+    fn outstanding(&self) -> usize {
+        (self.submitted - self.completed.load(Ordering::SeqCst)) as usize
+    }
+
+    fn retire_completed(&mut self) {
+        let outstanding = self.outstanding();
+        while self.submissions.len() > outstanding {
+            self.submissions.pop_front();
         }
     }
+}
 
-    pub async fn get<T>(&mut self, gpu: &GPUData<[T]>) -> Option<Box<[T]>>
-    where
-        T: bytemuck::Pod,
-    {
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-        encoder.copy_buffer_to_buffer(&gpu.storage_buffer, 0, &gpu.staging_buffer, 0, gpu.size);
-        self.queue.submit(Some(encoder.finish()));
+/// Transfer rates measured by `Device::measure_bandwidth`, in MB/s
+#[derive(Debug, Clone, Copy)]
+pub struct BandwidthReport {
+    pub bytes: u64,
+    pub host_to_device_mb_s: f64,
+    pub device_to_host_mb_s: f64,
+}
 
-        let buffer_slice = gpu.staging_buffer.slice(0..);
-        let buffer_future = buffer_slice.map_async(wgpu::MapMode::Read);
+///
+/// A bind group built once by `Device::create_bind_group` and reused across dispatches
+///
+pub struct CachedBindGroup {
+    set: u32,
+    bind_group: wgpu::BindGroup,
+}
 
-        self.device.poll(wgpu::Maintain::Wait);
+impl CachedBindGroup {
+    pub fn set(&self) -> u32 {
+        self.set
+    }
+}
 
-        // Gets contents of buffer
-        if let Ok(()) = buffer_future.await {
-            let data = buffer_slice.get_mapped_range();
-            let result = data
-                .chunks_exact(std::mem::size_of::<T>())
-                .map(|b| bytemuck::from_bytes::<T>(b).clone())
-                .collect();
-            drop(data);
-            gpu.staging_buffer.unmap();
-            return Some(result);
+impl GPUCompute {
+    /// Size in bytes of the push-constant range declared at compile time
+    pub fn push_constant_size(&self) -> u32 {
+        self.push_constant_size
+    }
+
+    /// A readable report of the pipeline's configuration (entry point, workgroup size and
+    /// the layout of every binding) to paste into bug reports
+    pub fn debug_dump(&self) -> String {
+        let mut dump = format!("entry point: {}\n", self.entry);
+        match self.local_size {
+            Some(size) => dump += &format!("workgroup size: {:?}\n", size),
+            None => dump += "workgroup size: unknown\n",
         }
-        None
+        let mut sets: Vec<&u32> = self.layouts.keys().collect();
+        sets.sort();
+        for set in sets {
+            dump += &format!("set {}\n", set);
+            let bindings = &self.layouts[set];
+            let mut binding_nums: Vec<&u32> = bindings.keys().collect();
+            binding_nums.sort();
+            for binding in binding_nums {
+                let (layout, type_name) = &bindings[binding];
+                dump += &format!("  binding {}: {} {:?}\n", binding, type_name, layout.ty);
+            }
+        }
+        dump
     }
 
-    pub fn compile(
+    /// Checks `args`, as passed to `call`, against the layout the pipeline was compiled with:
+    /// every declared binding needs an entry of the same kind of resource and there may be no
+    /// entries for undeclared bindings. `args` are the bindings of set 0, other sets have to be
+    /// empty. For SPIR-V shaders the layout of every storage buffer also has to be read-only
+    /// exactly when the shader declares it `readonly`, see `ParamsBuilder::param_readonly`.
+    ///
+    /// `call` only checks for missing bindings, this is a more thorough pre-flight check.
+    pub fn validate_args<'a>(
         &self,
-        entry: &str,
-        shader: &Vec<u32>,
-        params: &GPUSetGroupLayout,
-    ) -> Result<GPUCompute, ()> {
-        let mut bind_group_layouts: HashMap<u32, wgpu::BindGroupLayout> = HashMap::new();
-        let mut param_types = HashMap::new();
-
-        for (set_id, set) in &params.set_bind_group_layouts {
-            for (binding_num, binding) in set {
-                if !param_types.contains_key(&set_id) {
-                    param_types.insert(set_id, HashMap::new());
+        args: &HashMap<u32, wgpu::BindGroupEntry<'a>>,
+    ) -> Result<(), ValidationError> {
+        let mut sets: Vec<&u32> = self.layouts.keys().collect();
+        sets.sort();
+        for set in sets {
+            let bindings = &self.layouts[set];
+            let mut binding_nums: Vec<&u32> = bindings.keys().collect();
+            binding_nums.sort();
+            for binding in binding_nums {
+                let entry = match args.get(binding) {
+                    Some(entry) if *set == 0 => entry,
+                    _ => {
+                        return Err(ValidationError::MissingBinding {
+                            set: *set,
+                            binding: *binding,
+                        })
+                    }
+                };
+                if let (
+                    wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only },
+                        ..
+                    },
+                    Some(readonly),
+                ) = (bindings[binding].0.ty, &self.readonly)
+                {
+                    if read_only != readonly.contains(&(*set, *binding)) {
+                        return Err(ValidationError::ReadOnlyMismatch {
+                            set: *set,
+                            binding: *binding,
+                            layout_read_only: read_only,
+                        });
+                    }
+                }
+                let expected = match bindings[binding].0.ty {
+                    wgpu::BindingType::Buffer { .. } => "buffer",
+                    wgpu::BindingType::Sampler(..) => "sampler",
+                    _ => "texture view",
+                };
+                let found = match entry.resource {
+                    wgpu::BindingResource::Buffer(..) => "buffer",
+                    wgpu::BindingResource::Sampler(..) => "sampler",
+                    wgpu::BindingResource::TextureView(..) => "texture view",
+                    _ => "texture view array",
+                };
+                if expected != found {
+                    return Err(ValidationError::ResourceMismatch {
+                        binding: *binding,
+                        expected,
+                        found,
+                    });
                 }
-                param_types
-                    .get_mut(&set_id)
-                    .unwrap()
-                    .insert(*binding_num, binding.1.clone());
             }
-            bind_group_layouts.insert(
-                *set_id,
-                self.device
-                    .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-                        label: None,
-                        entries: set
-                            .values()
-                            .map(|binding_layout| binding_layout.0.clone())
-                            .collect::<Vec<wgpu::BindGroupLayoutEntry>>()
-                            .as_slice(),
-                    }),
-            );
         }
+        let mut extra: Vec<&u32> = args
+            .keys()
+            .filter(|binding| {
+                self.layouts
+                    .get(&0)
+                    .is_none_or(|bindings| !bindings.contains_key(binding))
+            })
+            .collect();
+        extra.sort();
+        match extra.first() {
+            Some(binding) => Err(ValidationError::ExtraBinding { binding: **binding }),
+            None => Ok(()),
+        }
+    }
 
-        let cs_module = self
-            .device
-            .create_shader_module(wgpu::ShaderModuleSource::SpirV(std::borrow::Cow::Borrowed(
-                shader,
-            )));
-
-        let pipeline_layout = self
-            .device
-            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: None,
-                bind_group_layouts: bind_group_layouts
-                    .values()
-                    .collect::<Vec<&wgpu::BindGroupLayout>>()
-                    .as_slice(),
-                push_constant_ranges: &[],
-            });
+    /// The workgroup size reflected from the shader's entry point, if it could be found
+    pub fn local_size(&self) -> Option<(u32, u32, u32)> {
+        self.local_size
+    }
+}
 
-        let pipeline = self
-            .device
-            .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
-                label: None,
-                layout: Some(&pipeline_layout),
-                compute_stage: wgpu::ProgrammableStageDescriptor {
-                    module: &cs_module,
-                    entry_point: entry,
-                },
-            });
+pub struct GPUSetGroupLayout {
+    pub set_bind_group_layouts: HashMap<u32, HashMap<u32, (wgpu::BindGroupLayoutEntry, String)>>,
+}
 
-        Ok(GPUCompute {
-            // param_types,
-            bind_group_layouts,
-            compute_pipeline: pipeline,
-        })
+impl GPUSetGroupLayout {
+    /// Combines the sets of two layouts, e.g. built by `ParamsBuilder::build(Some(0))` and
+    /// `ParamsBuilder::build(Some(1))`, into the layout of one pipeline
+    pub fn merge(mut self, other: GPUSetGroupLayout) -> Self {
+        self.set_bind_group_layouts
+            .extend(other.set_bind_group_layouts);
+        self
     }
 
-    pub fn call<'a>(
-        &mut self,
-        gpu_compute: GPUCompute,
-        workspace: (u32, u32, u32),
-        args: &HashMap<u32, wgpu::BindGroupEntry<'a>>,
-    ) {
-        let mut encoder = self
-            .device
-            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    /// Checks that `shader` declares exactly the bindings of this layout, see
+    /// `spirv::bindings`, so a mismatch is reported before `Device::compile` fails in wgpu
+    pub fn validate(&self, shader: &[u32]) -> Result<(), CompileError> {
+        let declared = crate::spirv::bindings(shader);
+        let mut layout: Vec<(u32, u32)> = self
+            .set_bind_group_layouts
+            .iter()
+            .flat_map(|(set, bindings)| bindings.keys().map(move |binding| (*set, *binding)))
+            .collect();
+        layout.sort();
 
-        let set_num = 0;
-        let mut bind_groups = vec![];
-        // for (set_num, bind_group) in &args {
-        bind_groups.push(
-            self.device.create_bind_group(&wgpu::BindGroupDescriptor {
-                label: None, // TODO maybe in all these label fields, we should actually use a label
-                layout: &gpu_compute.bind_group_layouts[&set_num],
-                entries: args
-                    .values()
-                    .map(|binding| binding.clone())
-                    .collect::<Vec<wgpu::BindGroupEntry>>()
-                    .as_slice(),
+        let missing = declared.iter().find(|b| !layout.contains(b));
+        let extra = layout.iter().find(|b| !declared.contains(b));
+        match missing.or(extra) {
+            Some((set, binding)) => Err(CompileError::BindingMismatch {
+                set: *set,
+                binding: *binding,
             }),
-        );
-        // }
-        {
-            let mut cpass = encoder.begin_compute_pass();
-            cpass.set_pipeline(&gpu_compute.compute_pipeline);
-
-            for (set_num, _bind_group) in gpu_compute.bind_group_layouts {
-                // bind_group = collection of bindings
-                // let offsets : Vec<u32>= (0..args.len()-1).map(|_| 0).collect();
-                cpass.set_bind_group(set_num, &bind_groups[set_num as usize], &[]);
-            }
-            cpass.dispatch(workspace.0, workspace.1, workspace.2);
+            None => Ok(()),
         }
-        self.queue.submit(Some(encoder.finish()));
     }
 }
 
-pub struct GPUCompute {
-    // param_types: HashMap<u32, HashMap<u32, String>>,
-    bind_group_layouts: HashMap<u32, wgpu::BindGroupLayout>,
-    compute_pipeline: wgpu::ComputePipeline,
-}
-
-pub struct GPUSetGroupLayout {
-    pub set_bind_group_layouts: HashMap<u32, HashMap<u32, (wgpu::BindGroupLayoutEntry, String)>>,
-}
-
 ///
 /// Helper to create the layout of bindings (along with set information.)
 /// This returns a `GPUSetGroupLayout` which is a HashMap with a key for a set,
@@ -323,6 +3077,12 @@ pub struct ParamsBuilder<'a> {
     pub binding_entry: HashMap<u32, wgpu::BindGroupEntry<'a>>,
 }
 
+impl Default for ParamsBuilder<'_> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl<'a> ParamsBuilder<'a> {
     pub fn new() -> Self {
         Self {
@@ -331,8 +3091,51 @@ impl<'a> ParamsBuilder<'a> {
         }
     }
 
-    pub fn param<T: Sized>(mut self, gpu_data: Option<&'a GPUData<[T]>>) -> Self {
-        let new_binding_layout_idx = self.binding_layouts.len() as u32;
+    /// Adds a storage buffer binding at the next binding index. The same `GPUData` may be
+    /// passed several times to alias it at several bindings, e.g. as both the input and the
+    /// output of an in-place pass. Aliases must all use `param`: wgpu rejects a buffer bound
+    /// as both `readonly` and writable storage in one dispatch.
+    pub fn param<T: Sized>(self, gpu_data: Option<&'a GPUData<[T]>>) -> Self {
+        self.storage(gpu_data, false)
+    }
+
+    /// Creates a storage buffer holding a single `u32` set to `initial`, e.g. for a GLSL
+    /// `buffer Counter { uint count; }` incremented with `atomicAdd`. Bind it with `param`
+    /// and set it back to zero between dispatches with `Device::reset_counter`.
+    pub fn counter(device: &mut Device, initial: u32) -> GPUData<[u32]> {
+        device.to_device(&[initial])
+    }
+
+    /// Like `param`, for buffers the shader declares `readonly`, so the layout matches the
+    /// GLSL qualifier
+    pub fn param_readonly<T: Sized>(self, gpu_data: Option<&'a GPUData<[T]>>) -> Self {
+        self.storage(gpu_data, true)
+    }
+
+    /// Like `param`, but places the buffer at `binding` instead of the next index, for shaders
+    /// with sparse bindings such as 0 and 3. Later calls to `param` continue after the highest
+    /// binding so far.
+    pub fn param_at<T: Sized>(self, binding: u32, gpu_data: Option<&'a GPUData<[T]>>) -> Self {
+        self.storage_at(binding, gpu_data, false)
+    }
+
+    /// One past the highest binding added so far, so the auto-numbered methods never
+    /// overwrite a binding placed with `param_at`
+    pub(crate) fn next_binding(&self) -> u32 {
+        self.binding_layouts.keys().max().map_or(0, |max| max + 1)
+    }
+
+    fn storage<T: Sized>(self, gpu_data: Option<&'a GPUData<[T]>>, readonly: bool) -> Self {
+        let binding = self.next_binding();
+        self.storage_at(binding, gpu_data, readonly)
+    }
+
+    fn storage_at<T: Sized>(
+        mut self,
+        new_binding_layout_idx: u32,
+        gpu_data: Option<&'a GPUData<[T]>>,
+        readonly: bool,
+    ) -> Self {
         // println!("{}", String::from(core::any::type_name::<T>()));
         // println!("{}",)
 
@@ -341,10 +3144,12 @@ impl<'a> ParamsBuilder<'a> {
             (
                 wgpu::BindGroupLayoutEntry {
                     binding: new_binding_layout_idx,
-                    visibility: wgpu::ShaderStage::COMPUTE,
-                    ty: wgpu::BindingType::StorageBuffer {
-                        dynamic: false,
-                        readonly: false,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage {
+                            read_only: readonly,
+                        },
+                        has_dynamic_offset: false,
                         min_binding_size: None,
                     },
                     count: None,
@@ -366,18 +3171,86 @@ impl<'a> ParamsBuilder<'a> {
         self
     }
 
+    /// Adds a uniform buffer binding for a GLSL `uniform` block, numbered along with the
+    /// storage buffers of `param`. `gpu_data` has to be created with
+    /// `Device::to_device_uniform` so its buffer can be bound as a uniform buffer, otherwise
+    /// this fails with `ValidationError::NotUniform`.
+    pub fn uniform<T: Sized>(
+        self,
+        gpu_data: Option<&'a GPUData<[T]>>,
+    ) -> Result<Self, ValidationError> {
+        self.uniform_binding(gpu_data, false)
+    }
+
+    /// Adds a uniform buffer binding with a dynamic offset, showing one element of `gpu_data`
+    /// at a time. The offset of each dispatch is given with `Dispatch::dynamic_offsets` or
+    /// `Device::call_with_dynamic_offsets` and has to be a multiple of
+    /// `min_uniform_buffer_offset_alignment` (usually 256 bytes), so `T` is typically padded
+    /// to that size. `gpu_data` has to be created with `Device::to_device_uniform`, as for
+    /// `uniform`.
+    pub fn uniform_dynamic<T: Sized>(
+        self,
+        gpu_data: Option<&'a GPUData<[T]>>,
+    ) -> Result<Self, ValidationError> {
+        self.uniform_binding(gpu_data, true)
+    }
+
+    fn uniform_binding<T: Sized>(
+        mut self,
+        gpu_data: Option<&'a GPUData<[T]>>,
+        dynamic: bool,
+    ) -> Result<Self, ValidationError> {
+        let new_binding_layout_idx = self.next_binding();
+        // A dynamic binding shows one element at a time, a static one the whole buffer
+        let size = if dynamic {
+            wgpu::BufferSize::new(std::mem::size_of::<T>() as u64)
+        } else {
+            None
+        };
+
+        if let Some(gpu) = gpu_data {
+            if !gpu.usage().contains(wgpu::BufferUsages::UNIFORM) {
+                return Err(ValidationError::NotUniform {
+                    binding: new_binding_layout_idx,
+                });
+            }
+            self.binding_entry.insert(
+                new_binding_layout_idx,
+                wgpu::BindGroupEntry {
+                    binding: new_binding_layout_idx,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: &gpu.storage_buffer,
+                        offset: 0,
+                        size,
+                    }),
+                },
+            );
+        }
+        self.binding_layouts.insert(
+            new_binding_layout_idx,
+            (
+                wgpu::BindGroupLayoutEntry {
+                    binding: new_binding_layout_idx,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: dynamic,
+                        min_binding_size: size,
+                    },
+                    count: None,
+                },
+                String::from(core::any::type_name::<T>()),
+            ),
+        );
+        Ok(self)
+    }
+
     pub fn build(
         self,
         set: Option<u32>,
     ) -> (GPUSetGroupLayout, HashMap<u32, wgpu::BindGroupEntry<'a>>) {
         let mut set_bind_group_layouts = HashMap::new();
-        set_bind_group_layouts.insert(
-            match set {
-                Some(s) => s,
-                None => 0,
-            },
-            self.binding_layouts,
-        );
+        set_bind_group_layouts.insert(set.unwrap_or(0), self.binding_layouts);
         (
             GPUSetGroupLayout {
                 set_bind_group_layouts,
@@ -390,31 +3263,115 @@ impl<'a> ParamsBuilder<'a> {
 #[cfg(not(target_arch = "wasm32"))]
 pub struct DeviceInfo {
     pub info: wgpu::AdapterInfo,
+    index: Option<usize>,
+    /// The maxima of the adapter for `query`, the limits the device was created with for
+    /// `Device::info`
+    limits: wgpu::Limits,
+    storage_formats: Vec<wgpu::TextureFormat>,
 }
 
+/// Formats which may be usable as storage textures, depending on the adapter
+#[cfg(not(target_arch = "wasm32"))]
+const STORAGE_FORMAT_CANDIDATES: &[wgpu::TextureFormat] = &[
+    wgpu::TextureFormat::R8Unorm,
+    wgpu::TextureFormat::R16Float,
+    wgpu::TextureFormat::R32Uint,
+    wgpu::TextureFormat::R32Sint,
+    wgpu::TextureFormat::R32Float,
+    wgpu::TextureFormat::Rg16Float,
+    wgpu::TextureFormat::Rg32Uint,
+    wgpu::TextureFormat::Rg32Sint,
+    wgpu::TextureFormat::Rg32Float,
+    wgpu::TextureFormat::Rgba8Unorm,
+    wgpu::TextureFormat::Rgba8Snorm,
+    wgpu::TextureFormat::Rgba8Uint,
+    wgpu::TextureFormat::Rgba8Sint,
+    wgpu::TextureFormat::Bgra8Unorm,
+    wgpu::TextureFormat::Rgb10a2Unorm,
+    wgpu::TextureFormat::Rg11b10Float,
+    wgpu::TextureFormat::Rgba16Uint,
+    wgpu::TextureFormat::Rgba16Sint,
+    wgpu::TextureFormat::Rgba16Float,
+    wgpu::TextureFormat::Rgba32Uint,
+    wgpu::TextureFormat::Rgba32Sint,
+    wgpu::TextureFormat::Rgba32Float,
+];
+
 #[cfg(not(target_arch = "wasm32"))]
 impl DeviceInfo {
+    fn from_adapter(adapter: &wgpu::Adapter, index: Option<usize>) -> Self {
+        let storage_formats = STORAGE_FORMAT_CANDIDATES
+            .iter()
+            .cloned()
+            .filter(|format| {
+                adapter
+                    .get_texture_format_features(*format)
+                    .allowed_usages
+                    .contains(wgpu::TextureUsages::STORAGE_BINDING)
+            })
+            .collect();
+        DeviceInfo {
+            info: adapter.get_info(),
+            index,
+            limits: adapter.limits(),
+            storage_formats,
+        }
+    }
+
+    /// Whether the adapter can use `format` for storage textures, see `Device::create_texture`
+    pub fn supports_storage_format(&self, format: wgpu::TextureFormat) -> bool {
+        self.storage_formats.contains(&format)
+    }
+    /// Position of the adapter in the enumeration of `query`, the index to pass to
+    /// `Device::new`. Indices are stable for one `wgpu::Instance`, see `query_with_instance`
+    /// and `Device::from_instance`. `None` for adapters picked by `Device::new_low_power`, or by
+    /// `Device::new_fallback` on a secondary backend.
+    pub fn index(&self) -> Option<usize> {
+        self.index
+    }
     pub fn name(&self) -> String {
         self.info.name.clone()
     }
     pub fn device_type(&self) -> wgpu::DeviceType {
-        self.info.device_type.clone()
+        self.info.device_type
     }
     pub fn backend(&self) -> &str {
         match self.info.backend {
             wgpu::Backend::Vulkan => "Vulkan",
             wgpu::Backend::Metal => "Metal",
-            wgpu::Backend::Dx11 => "Dx11",
+            wgpu::Backend::Gl => "Gl",
             wgpu::Backend::Dx12 => "Dx12",
             wgpu::Backend::BrowserWebGpu => "Browse",
             _ => "Unknown",
         }
     }
+    /// Whether this is an integrated GPU (or a CPU adapter), i.e. the power-saving choice
+    pub fn is_low_power(&self) -> bool {
+        matches!(
+            self.info.device_type,
+            wgpu::DeviceType::IntegratedGpu | wgpu::DeviceType::Cpu
+        )
+    }
     pub fn vendor_id(&self) -> usize {
-        self.info.vendor
+        self.info.vendor as usize
     }
     pub fn device_id(&self) -> usize {
-        self.info.device
+        self.info.device as usize
+    }
+    /// Most workgroups that can be dispatched along each dimension. Like the other limits,
+    /// this is the adapter's maximum for `query` results and the device's limit for
+    /// `Device::info`, usually lower unless the device was created with `Device::new_with`
+    /// asking for more.
+    pub fn max_compute_workgroups_per_dimension(&self) -> u32 {
+        self.limits.max_compute_workgroups_per_dimension
+    }
+    /// Largest storage buffer binding, in bytes
+    pub fn max_storage_buffer_binding_size(&self) -> u32 {
+        self.limits.max_storage_buffer_binding_size
+    }
+    /// Most invocations in one workgroup, i.e. the product of `local_size`
+    pub fn max_compute_invocations_per_workgroup(&self) -> u32 {
+        self.limits.max_compute_invocations_per_workgroup
     }
 }
 #[cfg(not(target_arch = "wasm32"))]
@@ -422,7 +3379,8 @@ impl std::fmt::Debug for DeviceInfo {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "{{ name: {:?}, vendor_id: {:?}, device_id: {:?}, device_type: {:?} }}",
+            "{{ index: {:?}, name: {:?}, vendor_id: {:?}, device_id: {:?}, device_type: {:?} }}",
+            self.index(),
             self.name(),
             self.vendor_id(),
             self.device_id(),
@@ -0,0 +1,395 @@
+use std::fmt;
+
+/// Returned when a wait is abandoned through a `CancelToken`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "wait was cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeviceError {
+    /// The instance enumerated no adapters at all, usually because no GPU driver is installed
+    NoAdaptersFound,
+    /// No backend enumerated any adapter at all
+    NoBackends,
+    /// There are adapters, but none at the requested index
+    AdapterNotFound(usize),
+    /// The adapter refused to create a device with the requested features and limits
+    RequestDeviceFailed,
+    /// The adapter lacks these of the features requested with `Device::new_with`
+    MissingFeatures(wgpu::Features),
+}
+
+impl fmt::Display for DeviceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DeviceError::NoAdaptersFound => write!(
+                f,
+                "no GPU adapters found, make sure Vulkan, Metal or DirectX 12 drivers are installed"
+            ),
+            DeviceError::NoBackends => write!(f, "no backend enumerated any adapter"),
+            DeviceError::AdapterNotFound(index) => write!(f, "no adapter at index {}", index),
+            DeviceError::RequestDeviceFailed => write!(f, "the adapter failed to create a device"),
+            DeviceError::MissingFeatures(features) => {
+                write!(f, "the adapter doesn't support {:?}", features)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DeviceError {}
+
+#[derive(Debug)]
+pub enum CallError {
+    /// The layout declares a binding that has no entry in the arguments of the call
+    MissingBinding { set: u32, binding: u32 },
+    /// wgpu reported a validation error while recording or submitting the dispatch
+    Validation(wgpu::Error),
+    /// The push constants don't match the size of the range declared by the pipeline
+    PushConstantSize { expected: u32, found: u32 },
+    /// The device ran out of memory on every attempt of `Device::call_with_retry`
+    OutOfMemory { attempts: u32, error: wgpu::Error },
+    /// A dispatch needs more workgroups along `axis` (0 = x, 1 = y, 2 = z) than the device allows
+    TooManyWorkgroups { axis: u32, groups: u32, max: u32 },
+    /// A dynamic offset is not a multiple of the device's `min_uniform_buffer_offset_alignment`
+    DynamicOffsetAlignment { offset: u32, alignment: u32 },
+    /// The number of dynamic offsets differs from the number of dynamic bindings of set 0
+    DynamicOffsetCount { expected: u32, found: u32 },
+    /// A dynamic offset moves the range of its binding past the end of the buffer
+    DynamicOffsetOutOfBounds {
+        binding: u32,
+        offset: u32,
+        buffer_size: u64,
+    },
+    /// The workgroup size of the entry point could not be reflected, see `GPUCompute::local_size`
+    UnknownLocalSize,
+    /// Two buffers that must hold the same number of elements, e.g. the keys and values of
+    /// `Device::reduce_by_key`, don't
+    LengthMismatch { expected: usize, found: usize },
+    /// None of the candidates of `Device::autotune` could be compiled, or none were given
+    NoCandidates,
+    /// A dispatch helper was given a workgroup size of 0 invocations along some axis
+    ZeroLocalSize,
+    /// A dispatch helper was given a different workgroup size than the shader declares
+    LocalSizeMismatch {
+        expected: (u32, u32, u32),
+        found: (u32, u32, u32),
+    },
+    /// The buffer of `Device::call_indirect` wasn't created with `Device::to_device_indirect`
+    NotIndirect,
+    /// The offset of `Device::call_indirect` is not a multiple of 4, or the three workgroup
+    /// counts at it run past the end of the buffer
+    IndirectOffset { offset: u64, buffer_size: u64 },
+    /// The kernel a helper like `Device::map_expr` generated didn't compile
+    Compile(CompileError),
+    /// Reading back a result the call depends on failed
+    Readback(GetError),
+    /// The buffer holds more elements than a bundled kernel can index
+    TooManyElements { len: usize, max: usize },
+}
+
+impl fmt::Display for CallError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CallError::MissingBinding { set, binding } => write!(
+                f,
+                "no buffer provided for set {} binding {} declared in the layout",
+                set, binding
+            ),
+            CallError::Validation(error) => write!(f, "validation error: {}", error),
+            CallError::PushConstantSize { expected, found } => write!(
+                f,
+                "pipeline declares {} bytes of push constants but {} were given",
+                expected, found
+            ),
+            CallError::OutOfMemory { attempts, error } => {
+                write!(f, "out of memory after {} attempts: {}", attempts, error)
+            }
+            CallError::TooManyWorkgroups { axis, groups, max } => write!(
+                f,
+                "{} workgroups along axis {} exceed the device limit of {}",
+                groups, axis, max
+            ),
+            CallError::DynamicOffsetAlignment { offset, alignment } => write!(
+                f,
+                "dynamic offset {} is not a multiple of {} bytes",
+                offset, alignment
+            ),
+            CallError::DynamicOffsetCount { expected, found } => write!(
+                f,
+                "the layout has {} dynamic bindings but {} offsets were given",
+                expected, found
+            ),
+            CallError::DynamicOffsetOutOfBounds {
+                binding,
+                offset,
+                buffer_size,
+            } => write!(
+                f,
+                "dynamic offset {} of binding {} runs past the end of its {} byte buffer",
+                offset, binding, buffer_size
+            ),
+            CallError::UnknownLocalSize => {
+                write!(f, "the workgroup size of the entry point is unknown")
+            }
+            CallError::LengthMismatch { expected, found } => write!(
+                f,
+                "buffers hold {} and {} elements but must be the same length",
+                expected, found
+            ),
+            CallError::NoCandidates => write!(f, "no candidate local size could be compiled"),
+            CallError::ZeroLocalSize => write!(f, "workgroups must have at least one invocation"),
+            CallError::LocalSizeMismatch { expected, found } => write!(
+                f,
+                "the shader declares a workgroup size of {:?} but {:?} was given",
+                expected, found
+            ),
+            CallError::NotIndirect => write!(
+                f,
+                "indirect dispatch buffers must be created with to_device_indirect"
+            ),
+            CallError::IndirectOffset {
+                offset,
+                buffer_size,
+            } => write!(
+                f,
+                "indirect offset {} is unaligned or out of bounds of its {} byte buffer",
+                offset, buffer_size
+            ),
+            CallError::Compile(error) => write!(f, "could not compile the kernel: {}", error),
+            CallError::Readback(error) => write!(f, "could not read the result back: {}", error),
+            CallError::TooManyElements { len, max } => write!(
+                f,
+                "{} elements exceed the {} a bundled kernel can index",
+                len, max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for CallError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            CallError::Validation(error) => Some(error),
+            CallError::OutOfMemory { error, .. } => Some(error),
+            CallError::Compile(error) => Some(error),
+            CallError::Readback(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Why the arguments of a call don't fit a pipeline, see `GPUCompute::validate_args`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ValidationError {
+    /// The layout declares a binding that has no entry in the arguments
+    MissingBinding { set: u32, binding: u32 },
+    /// The arguments have an entry for a binding the layout doesn't declare
+    ExtraBinding { binding: u32 },
+    /// The entry of a binding is a different kind of resource than the layout declares
+    ResourceMismatch {
+        binding: u32,
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// The buffer bound as a uniform wasn't created with `Device::to_device_uniform`
+    NotUniform { binding: u32 },
+    /// The layout of a storage buffer is read-only but the shader doesn't declare it
+    /// `readonly`, or the other way around
+    ReadOnlyMismatch {
+        set: u32,
+        binding: u32,
+        layout_read_only: bool,
+    },
+}
+
+impl fmt::Display for ValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ValidationError::MissingBinding { set, binding } => write!(
+                f,
+                "no resource provided for set {} binding {} declared in the layout",
+                set, binding
+            ),
+            ValidationError::ExtraBinding { binding } => write!(
+                f,
+                "binding {} is not declared in the layout of set 0",
+                binding
+            ),
+            ValidationError::ResourceMismatch {
+                binding,
+                expected,
+                found,
+            } => write!(
+                f,
+                "binding {} expects a {} but a {} was given",
+                binding, expected, found
+            ),
+            ValidationError::NotUniform { binding } => write!(
+                f,
+                "binding {} is a uniform but its buffer wasn't created with to_device_uniform",
+                binding
+            ),
+            ValidationError::ReadOnlyMismatch {
+                set,
+                binding,
+                layout_read_only,
+            } => write!(
+                f,
+                "set {} binding {} is {} in the layout but not in the shader",
+                set,
+                binding,
+                if *layout_read_only {
+                    "read-only"
+                } else {
+                    "writable"
+                }
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ValidationError {}
+
+/// Why a shader could not be compiled, e.g. because it doesn't fit its layout, see
+/// `GPUSetGroupLayout::validate`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CompileError {
+    /// The binding is declared by only one of the shader and the layout
+    BindingMismatch { set: u32, binding: u32 },
+    /// The WGSL source doesn't parse, with the diagnostic of the WGSL front end
+    InvalidWgsl(String),
+    /// shaderc rejected the GLSL source of `Device::compile_glsl`, with its error messages
+    InvalidGlsl(String),
+    /// `Device::compile_glsl` was asked for a stage other than compute
+    UnsupportedStage(wgpu::ShaderStages),
+    /// The shader needs a feature the device wasn't created with
+    MissingFeature(wgpu::Features),
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CompileError::BindingMismatch { set, binding } => write!(
+                f,
+                "set {} binding {} is not declared by both the shader and the layout",
+                set, binding
+            ),
+            CompileError::InvalidWgsl(message) => write!(f, "invalid WGSL: {}", message),
+            CompileError::InvalidGlsl(message) => write!(f, "invalid GLSL: {}", message),
+            CompileError::UnsupportedStage(stage) => {
+                write!(f, "only compute shaders are supported, not {:?}", stage)
+            }
+            CompileError::MissingFeature(feature) => {
+                write!(f, "the device doesn't support {:?}", feature)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CompileError {}
+
+/// Why `load_shader` could not read a SPIR-V module
+#[derive(Debug)]
+pub enum ShaderLoadError {
+    /// The file could not be read
+    Io(std::io::Error),
+    /// The file doesn't start with the SPIR-V magic number, e.g. because it is GLSL source
+    NotSpirv { found_magic: u32 },
+    /// The length of the file is not a whole number of 4 byte words
+    UnalignedLength,
+}
+
+impl fmt::Display for ShaderLoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderLoadError::Io(error) => write!(f, "could not read shader: {}", error),
+            ShaderLoadError::NotSpirv { found_magic } => write!(
+                f,
+                "wrong magic word {:x}, make sure you are using a binary SPIR-V file",
+                found_magic
+            ),
+            ShaderLoadError::UnalignedLength => {
+                write!(f, "SPIR-V file length is not a multiple of 4 bytes")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ShaderLoadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ShaderLoadError::Io(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// Why `Device::get` could not read a buffer back
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GetError {
+    /// The storage buffer was created without `COPY_SRC`, see `GPUData::is_readable`
+    NotReadable,
+    /// Mapping the buffer for reading failed
+    MapFailed(wgpu::BufferAsyncError),
+    /// The device was lost, e.g. after a driver reset, and its buffers can't be read anymore
+    DeviceLost,
+    /// The buffer and its destination don't hold the same number of elements, e.g. the
+    /// output slice of `Device::get_into`
+    SizeMismatch { expected: usize, found: usize },
+    /// The elements `start..end` don't lie within the `len` elements of the buffer, see
+    /// `Device::get_range`
+    OutOfBounds {
+        start: usize,
+        end: usize,
+        len: usize,
+    },
+    /// The wait was abandoned through a `CancelToken`, see `Device::get_cancellable`
+    Cancelled,
+}
+
+impl fmt::Display for GetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GetError::NotReadable => write!(f, "the buffer cannot be read back"),
+            GetError::MapFailed(error) => write!(f, "failed to map the buffer: {}", error),
+            GetError::DeviceLost => write!(f, "the device was lost"),
+            GetError::SizeMismatch { expected, found } => {
+                write!(f, "expected {} elements, found {}", expected, found)
+            }
+            GetError::OutOfBounds { start, end, len } => write!(
+                f,
+                "range {}..{} is out of bounds of {} elements",
+                start, end, len
+            ),
+            GetError::Cancelled => write!(f, "{}", Cancelled),
+        }
+    }
+}
+
+impl From<GetError> for CallError {
+    fn from(error: GetError) -> Self {
+        CallError::Readback(error)
+    }
+}
+
+impl From<Cancelled> for GetError {
+    fn from(_: Cancelled) -> Self {
+        GetError::Cancelled
+    }
+}
+
+impl std::error::Error for GetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            GetError::MapFailed(error) => Some(error),
+            _ => None,
+        }
+    }
+}
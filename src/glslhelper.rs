@@ -1,18 +1,83 @@
-#[cfg(feature = "shaderc")]
+#[cfg(feature = "glsl")]
+use crate::device::*;
+#[cfg(feature = "glsl")]
+use crate::error::CompileError;
+
+///
+/// A non-fatal message reported by a shader frontend while compiling, e.g. a warning
+/// about a deprecated construct or an unused variable.
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub line: Option<u32>,
+    pub message: String,
+}
+
+#[cfg(feature = "glsl")]
+impl Diagnostic {
+    /// Parses the `name:line: warning: message` lines produced by shaderc, skipping its
+    /// trailing summary like `1 warning generated.`
+    fn parse_warnings(text: &str) -> Vec<Diagnostic> {
+        text.lines()
+            .filter(|line| !line.trim().is_empty() && !line.trim_end().ends_with(" generated."))
+            .map(|line| match line.find(": warning: ") {
+                Some(idx) => Diagnostic {
+                    line: line[..idx]
+                        .rsplit(':')
+                        .next()
+                        .and_then(|l| l.trim().parse().ok()),
+                    message: line[idx + ": warning: ".len()..].to_string(),
+                },
+                None => Diagnostic {
+                    line: None,
+                    message: line.to_string(),
+                },
+            })
+            .collect()
+    }
+}
+
+///
+/// Successful output of a shader compilation along with any warnings emitted by the frontend.
+///
+#[derive(Debug, Clone)]
+pub struct CompileOutput {
+    pub spirv: Vec<u32>,
+    pub warnings: Vec<Diagnostic>,
+}
+
+#[cfg(feature = "glsl")]
+pub use shaderc::SpirvVersion;
+
+#[cfg(feature = "glsl")]
 pub struct GLSLCompile {
     code: String,
     compiler: shaderc::Compiler,
+    target_spirv: SpirvVersion,
 }
 
-#[cfg(feature = "shaderc")]
+#[cfg(feature = "glsl")]
 impl GLSLCompile {
     pub fn new(code: &str) -> Self {
         GLSLCompile {
             code: code.to_string(),
             compiler: shaderc::Compiler::new().unwrap(),
+            target_spirv: SpirvVersion::V1_0,
         }
     }
-    pub fn compile(&mut self, entry: &str) -> Result<Vec<u32>, ()> {
+    /// Sets the SPIR-V version to emit. Newer versions enable more features but may be
+    /// rejected by older drivers, so SPIR-V 1.0 is targeted by default.
+    pub fn with_target_spirv(mut self, version: SpirvVersion) -> Self {
+        self.target_spirv = version;
+        self
+    }
+    /// Compiles the source to SPIR-V, or returns the error messages of shaderc
+    pub fn compile(&mut self, entry: &str) -> Result<Vec<u32>, String> {
+        self.compile_with_diagnostics(entry).map(|out| out.spirv)
+    }
+    pub fn compile_with_diagnostics(&mut self, entry: &str) -> Result<CompileOutput, String> {
+        let mut options = shaderc::CompileOptions::new().unwrap();
+        options.set_target_spirv(self.target_spirv);
         let bin = self
             .compiler
             .compile_into_spirv(
@@ -20,9 +85,40 @@ impl GLSLCompile {
                 shaderc::ShaderKind::Compute,
                 "name",
                 entry,
-                None,
+                Some(&options),
             )
-            .unwrap();
-        Ok(bin.as_binary().to_vec())
+            .map_err(|error| error.to_string())?;
+        let warnings = if bin.get_num_warnings() > 0 {
+            Diagnostic::parse_warnings(&bin.get_warning_messages())
+        } else {
+            vec![]
+        };
+        Ok(CompileOutput {
+            spirv: bin.as_binary().to_vec(),
+            warnings,
+        })
+    }
+}
+
+#[cfg(feature = "glsl")]
+impl Device {
+    /// Compiles the GLSL `source` to SPIR-V with shaderc at runtime and builds its pipeline,
+    /// so `.comp` sources can be shipped instead of precompiled binaries. Enabled by the `glsl`
+    /// feature; only `ShaderStages::COMPUTE` is supported, other stages fail with
+    /// `CompileError::UnsupportedStage`.
+    pub fn compile_glsl(
+        &self,
+        source: &str,
+        stage: wgpu::ShaderStages,
+        entry: &str,
+        params: &GPUSetGroupLayout,
+    ) -> Result<GPUCompute, CompileError> {
+        if stage != wgpu::ShaderStages::COMPUTE {
+            return Err(CompileError::UnsupportedStage(stage));
+        }
+        let spirv = GLSLCompile::new(source)
+            .compile(entry)
+            .map_err(CompileError::InvalidGlsl)?;
+        self.compile(entry, &spirv, params)
     }
 }
@@ -0,0 +1,220 @@
+use crate::device::*;
+use crate::error::*;
+use std::collections::HashMap;
+
+/// Bind group entries of a kernel, passed to `Device::call`
+pub type Args<'a> = HashMap<u32, wgpu::BindGroupEntry<'a>>;
+
+///
+/// Builds the parameters of a kernel and compiles it in one chain, so the layout given to
+/// `compile` and the entries given to `call` can't get out of sync.
+/// ```ignore
+///     let (compute, args) = device
+///         .kernel(&shader)
+///         .entry("main")
+///         .param(&data_gpu)
+///         .build()
+///         .unwrap();
+///     device.call(&compute, (arr.len() as u32, 1, 1), &args).unwrap();
+/// ```
+///
+pub struct KernelBuilder<'d, 'a> {
+    device: &'d Device,
+    shader: &'d Vec<u32>,
+    entry: String,
+    params: ParamsBuilder<'a>,
+}
+
+impl<'d, 'a> KernelBuilder<'d, 'a> {
+    /// Sets the entry point, `main` by default
+    pub fn entry(mut self, entry: &str) -> Self {
+        self.entry = entry.to_string();
+        self
+    }
+
+    /// Binds `gpu_data` at the next binding of set 0
+    pub fn param<T: Sized>(mut self, gpu_data: &'a GPUData<[T]>) -> Self {
+        self.params = self.params.param(Some(gpu_data));
+        self
+    }
+
+    /// Binds `gpu_data` at the next binding of set 0 as a `readonly` buffer
+    pub fn param_readonly<T: Sized>(mut self, gpu_data: &'a GPUData<[T]>) -> Self {
+        self.params = self.params.param_readonly(Some(gpu_data));
+        self
+    }
+
+    pub fn build(self) -> Result<(GPUCompute, Args<'a>), CompileError> {
+        let (layout, args) = self.params.build(Some(0));
+        let compute = self.device.compile(&self.entry, self.shader, &layout)?;
+        Ok((compute, args))
+    }
+}
+
+impl Device {
+    /// Starts configuring a dispatch of `compute`
+    pub fn dispatch<'d, 'a>(&'d mut self, compute: &'d GPUCompute) -> Dispatch<'d, 'a> {
+        Dispatch {
+            device: self,
+            compute,
+            push_constants: vec![],
+            dynamic_offsets: vec![],
+            groups: (1, 1, 1),
+            args: None,
+        }
+    }
+
+    pub fn kernel<'d, 'a>(&'d self, shader: &'d Vec<u32>) -> KernelBuilder<'d, 'a> {
+        KernelBuilder {
+            device: self,
+            shader,
+            entry: String::from("main"),
+            params: ParamsBuilder::new(),
+        }
+    }
+}
+
+///
+/// Per-dispatch configuration of a compiled kernel, obtained from `Device::dispatch`.
+/// ```ignore
+///     device
+///         .dispatch(&compute)
+///         .push(Params { scale: 2.0, count: 64 })
+///         .groups(64, 1, 1)
+///         .args(&args)
+///         .run()
+///         .unwrap();
+/// ```
+///
+pub struct Dispatch<'d, 'a> {
+    device: &'d mut Device,
+    compute: &'d GPUCompute,
+    push_constants: Vec<u8>,
+    dynamic_offsets: Vec<u32>,
+    groups: (u32, u32, u32),
+    args: Option<&'d Args<'a>>,
+}
+
+impl<'d, 'a> Dispatch<'d, 'a> {
+    /// Sets the push constants of the dispatch to the bytes of `params`, which must match
+    /// the size declared with `Device::compile_with_push_constants`
+    pub fn push<P: bytemuck::Pod>(mut self, params: P) -> Self {
+        self.push_constants = bytemuck::bytes_of(&params).to_vec();
+        self
+    }
+
+    /// Like `push`, for push constants that are already bytes
+    pub(crate) fn push_bytes(mut self, bytes: &[u8]) -> Self {
+        self.push_constants = bytes.to_vec();
+        self
+    }
+
+    /// Number of workgroups along each dimension, `(1, 1, 1)` by default
+    pub fn groups(mut self, x: u32, y: u32, z: u32) -> Self {
+        self.groups = (x, y, z);
+        self
+    }
+
+    /// Offsets in bytes into the dynamic bindings of set 0, in binding order, e.g. to pick
+    /// this dispatch's parameters from a `ParamsBuilder::uniform_dynamic` buffer
+    pub fn dynamic_offsets(mut self, offsets: &[u32]) -> Self {
+        self.dynamic_offsets = offsets.to_vec();
+        self
+    }
+
+    pub fn args(mut self, args: &'d Args<'a>) -> Self {
+        self.args = Some(args);
+        self
+    }
+
+    pub fn run(self) -> Result<wgpu::SubmissionIndex, CallError> {
+        let push_constants = push_constant_words(self.compute, &self.push_constants)?;
+
+        let empty = Args::new();
+        let args = self.args.unwrap_or(&empty);
+        self.device.record_and_submit(
+            self.compute,
+            self.groups.into(),
+            &single_set(args),
+            &[],
+            &push_constants,
+            &self.dynamic_offsets,
+        )
+    }
+}
+
+///
+/// Bind group entries built once by `Device::make_bindings` and passed to many calls of
+/// pipelines compiled against the same layout. Derefs to `Args`.
+/// ```ignore
+///     let bindings = device
+///         .make_bindings(&layout, &[&input.storage_buffer, &output.storage_buffer])
+///         .unwrap();
+///     device.call(&first, (64, 1, 1), &bindings).unwrap();
+///     device.call(&second, (64, 1, 1), &bindings).unwrap();
+/// ```
+///
+pub struct ReusableArgs<'a> {
+    args: Args<'a>,
+}
+
+impl<'a> std::ops::Deref for ReusableArgs<'a> {
+    type Target = Args<'a>;
+
+    fn deref(&self) -> &Args<'a> {
+        &self.args
+    }
+}
+
+impl Device {
+    /// Binds `bufs` in order to the bindings of set 0 of `layout`, checking that there is a
+    /// buffer for every binding, no buffer too many, and that all bindings are buffers
+    pub fn make_bindings<'a>(
+        &self,
+        layout: &GPUSetGroupLayout,
+        bufs: &[&'a wgpu::Buffer],
+    ) -> Result<ReusableArgs<'a>, ValidationError> {
+        let empty = HashMap::new();
+        let bindings = layout.set_bind_group_layouts.get(&0).unwrap_or(&empty);
+        let mut binding_nums: Vec<&u32> = bindings.keys().collect();
+        binding_nums.sort();
+
+        if bufs.len() > binding_nums.len() {
+            return Err(ValidationError::ExtraBinding {
+                binding: binding_nums.last().map_or(0, |b| **b + 1),
+            });
+        }
+        let mut args = Args::new();
+        for (i, binding) in binding_nums.into_iter().enumerate() {
+            let buffer = match bufs.get(i) {
+                Some(buffer) => buffer,
+                None => {
+                    return Err(ValidationError::MissingBinding {
+                        set: 0,
+                        binding: *binding,
+                    })
+                }
+            };
+            let expected = match bindings[binding].0.ty {
+                wgpu::BindingType::Buffer { .. } => "buffer",
+                wgpu::BindingType::Sampler(..) => "sampler",
+                _ => "texture view",
+            };
+            if expected != "buffer" {
+                return Err(ValidationError::ResourceMismatch {
+                    binding: *binding,
+                    expected,
+                    found: "buffer",
+                });
+            }
+            args.insert(
+                *binding,
+                wgpu::BindGroupEntry {
+                    binding: *binding,
+                    resource: buffer.as_entire_binding(),
+                },
+            );
+        }
+        Ok(ReusableArgs { args })
+    }
+}
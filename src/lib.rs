@@ -1,6 +1,38 @@
+pub mod accumulator;
+pub mod batch;
+pub mod buffer;
 pub mod device;
+pub mod error;
 pub mod glslhelper;
+pub mod kernel;
+pub mod map;
+#[cfg(feature = "ndarray")]
+pub mod ndarray;
+#[cfg(feature = "glsl")]
+pub mod ops;
+pub mod reduce;
+pub mod spirv;
+pub mod texture;
+#[cfg(not(target_arch = "wasm32"))]
+pub mod worker;
 
+#[cfg(feature = "ndarray")]
+pub use self::ndarray::*;
+pub use accumulator::*;
+#[cfg(feature = "derive")]
+pub use alkomp_derive::GpuBuffer;
+pub use batch::*;
+pub use buffer::*;
 pub use device::*;
+pub use error::*;
+pub use kernel::*;
+pub use map::*;
+#[cfg(feature = "glsl")]
+pub use ops::*;
+pub use reduce::ReduceProgress;
+pub use spirv::*;
+pub use texture::*;
+#[cfg(not(target_arch = "wasm32"))]
+pub use worker::*;
 
 pub use glslhelper::*;
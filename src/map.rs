@@ -0,0 +1,93 @@
+use crate::device::*;
+use crate::error::*;
+use futures::executor::block_on;
+
+///
+/// Element types `Device::map_expr` can substitute into its WGSL template
+///
+pub trait WgslScalar: bytemuck::Pod {
+    /// The WGSL name of the type
+    const WGSL_TYPE: &'static str;
+}
+
+impl WgslScalar for f32 {
+    const WGSL_TYPE: &'static str = "f32";
+}
+
+impl WgslScalar for u32 {
+    const WGSL_TYPE: &'static str = "u32";
+}
+
+impl WgslScalar for i32 {
+    const WGSL_TYPE: &'static str = "i32";
+}
+
+/// Workgroup size of the `map_expr` kernel
+const MAP_LOCAL_SIZE: u32 = 64;
+
+/// Applies `EXPR` to every element `x` of `data` in place. Rows of workgroups along y
+/// continue where the previous row ended, for more elements than one dispatch row covers.
+const MAP_EXPR: &str = "
+@group(0) @binding(0) var<storage, read_write> data: array<ELEMENT>;
+
+@compute @workgroup_size(64)
+fn main(
+    @builtin(global_invocation_id) id: vec3<u32>,
+    @builtin(num_workgroups) groups: vec3<u32>,
+) {
+    let i = id.y * groups.x * 64u + id.x;
+    if (i >= arrayLength(&data)) {
+        return;
+    }
+    let x = data[i];
+    data[i] = EXPR;
+}";
+
+impl Device {
+    ///
+    /// Replaces every element `x` of `gpu` with the WGSL expression `expr`, e.g.
+    /// `"x * 2.0 + 1.0"` for `f32` data or `"min(x, 255u)"` for `u32`, without writing a
+    /// shader. The expression must have the element type.
+    ///
+    /// `expr` is pasted verbatim into a WGSL template, so it has to be a single expression;
+    /// one containing `;`, `{` or `}` is rejected. The pipeline is compiled on the first call
+    /// and cached by expression and element type for the lifetime of the device, so calling
+    /// this in a loop only compiles once. An expression that doesn't compile fails with
+    /// `CallError::Compile`.
+    ///
+    pub fn map_expr<T: WgslScalar>(
+        &mut self,
+        gpu: &GPUData<[T]>,
+        expr: &str,
+    ) -> Result<wgpu::SubmissionIndex, CallError> {
+        let args = ParamsBuilder::new().param(Some(gpu)).build(Some(0));
+        let key = (expr.to_string(), T::WGSL_TYPE);
+        let compute = match self.map_pipelines.get(&key) {
+            Some(compute) => compute.clone(),
+            None => {
+                if expr.contains(&[';', '{', '}'][..]) {
+                    return Err(CallError::Compile(CompileError::InvalidWgsl(format!(
+                        "`{}` is not a single expression",
+                        expr
+                    ))));
+                }
+                let source = MAP_EXPR
+                    .replace("ELEMENT", T::WGSL_TYPE)
+                    .replace("EXPR", expr);
+                self.device.push_error_scope(wgpu::ErrorFilter::Validation);
+                let compute = self.compile_wgsl("main", &source, &args.0);
+                if let Some(error) = block_on(self.device.pop_error_scope()) {
+                    return Err(CallError::Compile(CompileError::InvalidWgsl(
+                        error.to_string(),
+                    )));
+                }
+                let compute = compute.map_err(CallError::Compile)?;
+                self.map_pipelines.insert(key, compute.clone());
+                compute
+            }
+        };
+
+        let workspace = self.workgroup_rows(workgroup_count(gpu.len() as u32, MAP_LOCAL_SIZE))?;
+        self.call(&compute, workspace, &args.1)
+    }
+}
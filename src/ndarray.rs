@@ -0,0 +1,65 @@
+use crate::device::*;
+use crate::error::*;
+use ::ndarray::{Array, ArrayBase, Data, Dimension};
+
+///
+/// An n-dimensional array on the device: its elements in standard (row-major) layout and
+/// its shape as `u32`s, to bind next to each other, e.g. as `float[] data` and `uint[] dim`.
+/// ```ignore
+///     let arr = ndarray::Array::<f32, _>::ones((2, 3));
+///     let gpu = device.to_device_array(&arr);
+///     let args = alkomp::ParamsBuilder::new()
+///         .param(Some(&gpu.data))
+///         .param(Some(&gpu.shape))
+///         .build(Some(0));
+///     // ...
+///     let result = futures::executor::block_on(device.get_array(&gpu)).unwrap();
+/// ```
+///
+pub struct GpuArray<A, D> {
+    pub data: GPUData<[A]>,
+    pub shape: GPUData<[u32]>,
+    dim: D,
+}
+
+impl<A, D: Dimension> GpuArray<A, D> {
+    /// The shape of the array on the host
+    pub fn dim(&self) -> &D {
+        &self.dim
+    }
+}
+
+impl Device {
+    /// Uploads the elements and the shape of `arr`. Arrays that aren't in standard layout,
+    /// e.g. transposed views, are copied into it first.
+    pub fn to_device_array<A, S, D>(&mut self, arr: &ArrayBase<S, D>) -> GpuArray<A, D>
+    where
+        A: bytemuck::Pod,
+        S: Data<Elem = A>,
+        D: Dimension,
+    {
+        let standard = arr.as_standard_layout();
+        let shape: Vec<u32> = arr.shape().iter().map(|len| *len as u32).collect();
+        GpuArray {
+            data: self.to_device(standard.as_slice().unwrap()),
+            shape: self.to_device(shape.as_slice()),
+            dim: arr.raw_dim(),
+        }
+    }
+
+    /// Reads the elements of `gpu` back into an array of its shape
+    pub async fn get_array<A, D>(&mut self, gpu: &GpuArray<A, D>) -> Result<Array<A, D>, GetError>
+    where
+        A: bytemuck::Pod,
+        D: Dimension,
+    {
+        let data = self.get(&gpu.data).await?;
+        let found = data.len();
+        Array::from_shape_vec(gpu.dim.clone(), data.into_vec()).map_err(|_| {
+            GetError::SizeMismatch {
+                expected: gpu.dim.size(),
+                found,
+            }
+        })
+    }
+}
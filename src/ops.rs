@@ -0,0 +1,725 @@
+use crate::device::*;
+use crate::error::*;
+use crate::glslhelper::GLSLCompile;
+use crate::reduce::ReduceProgress;
+use futures::executor::block_on;
+
+/// Workgroup size of the bundled kernels
+const LOCAL_SIZE: u32 = 64;
+
+/// Binary operation used to combine values in reductions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReduceOp {
+    Sum,
+    Min,
+    Max,
+}
+
+impl ReduceOp {
+    fn glsl(&self) -> &'static str {
+        match self {
+            ReduceOp::Sum => "((a) + (b))",
+            ReduceOp::Min => "min((a), (b))",
+            ReduceOp::Max => "max((a), (b))",
+        }
+    }
+}
+
+///
+/// Element types the bundled reduction kernels can be instantiated for
+///
+pub trait GpuScalar: bytemuck::Pod {
+    /// The GLSL name of the type
+    const GLSL_TYPE: &'static str;
+    /// GLSL expression of the identity element of `op`
+    fn identity(op: ReduceOp) -> &'static str;
+    /// The identity element of `op`, the reduction of an empty buffer
+    fn identity_value(op: ReduceOp) -> Self;
+}
+
+impl GpuScalar for f32 {
+    const GLSL_TYPE: &'static str = "float";
+    fn identity(op: ReduceOp) -> &'static str {
+        match op {
+            ReduceOp::Sum => "0.0",
+            ReduceOp::Min => "uintBitsToFloat(0x7f800000u)",
+            ReduceOp::Max => "uintBitsToFloat(0xff800000u)",
+        }
+    }
+    fn identity_value(op: ReduceOp) -> Self {
+        match op {
+            ReduceOp::Sum => 0.0,
+            ReduceOp::Min => f32::INFINITY,
+            ReduceOp::Max => f32::NEG_INFINITY,
+        }
+    }
+}
+
+impl GpuScalar for u32 {
+    const GLSL_TYPE: &'static str = "uint";
+    fn identity(op: ReduceOp) -> &'static str {
+        match op {
+            ReduceOp::Sum => "0u",
+            ReduceOp::Min => "0xffffffffu",
+            ReduceOp::Max => "0u",
+        }
+    }
+    fn identity_value(op: ReduceOp) -> Self {
+        match op {
+            ReduceOp::Sum => 0,
+            ReduceOp::Min => u32::MAX,
+            ReduceOp::Max => 0,
+        }
+    }
+}
+
+impl GpuScalar for i32 {
+    const GLSL_TYPE: &'static str = "int";
+    fn identity(op: ReduceOp) -> &'static str {
+        match op {
+            ReduceOp::Sum => "0",
+            ReduceOp::Min => "0x7fffffff",
+            ReduceOp::Max => "(-0x7fffffff - 1)",
+        }
+    }
+    fn identity_value(op: ReduceOp) -> Self {
+        match op {
+            ReduceOp::Sum => 0,
+            ReduceOp::Min => i32::MAX,
+            ReduceOp::Max => i32::MIN,
+        }
+    }
+}
+
+/// One pass of a tree reduction: each workgroup combines its elements into one output, for
+/// `Device::reduce_passes`
+const REDUCE_PASS: &str = "
+#version 450
+layout(local_size_x = 256) in;
+
+layout(set = 0, binding = 0) buffer Params { uint n; uint m; uint j; uint k; };
+layout(set = 0, binding = 1) buffer In { T data_in[]; };
+layout(set = 0, binding = 2) buffer Out { T data_out[]; };
+
+shared T partial[256];
+
+void main() {
+    uint group = gl_WorkGroupID.y * gl_NumWorkGroups.x + gl_WorkGroupID.x;
+    uint local = gl_LocalInvocationID.x;
+    uint i = group * 256 + local;
+    partial[local] = i < n ? data_in[i] : IDENTITY;
+    barrier();
+    for (uint stride = 128; stride > 0; stride >>= 1) {
+        if (local < stride) {
+            partial[local] = OP(partial[local], partial[local + stride]);
+        }
+        barrier();
+    }
+    if (local == 0) {
+        data_out[group] = partial[0];
+    }
+}";
+
+/// Raises the mismatch flag if any word of the two buffers differs, dispatched in rows of
+/// workgroups, see `Device::workgroup_rows`
+const COMPARE_WORDS: &str = "
+#version 450
+layout(local_size_x = 64) in;
+
+layout(set = 0, binding = 0) buffer Params { uint n; uint m; uint j; uint k; };
+layout(set = 0, binding = 1) buffer A { uint a[]; };
+layout(set = 0, binding = 2) buffer B { uint b[]; };
+layout(set = 0, binding = 3) buffer Mismatch { uint mismatch; };
+
+void main() {
+    uint i = gl_GlobalInvocationID.y * gl_NumWorkGroups.x * 64 + gl_GlobalInvocationID.x;
+    if (i < n && a[i] != b[i]) {
+        atomicOr(mismatch, 1u);
+    }
+}";
+
+/// Writes the index of every element into it
+const IOTA: &str = "
+#version 450
+layout(local_size_x = 64) in;
+
+layout(set = 0, binding = 0) buffer Indices { uint indices[]; };
+
+void main() {
+    uint i = gl_GlobalInvocationID.x;
+    if (i < indices.length()) {
+        indices[i] = i;
+    }
+}";
+
+/// Counter-based uniform random numbers: each value only depends on its index and the seed.
+/// Dispatched in rows of workgroups, see `Device::workgroup_rows`
+const RANDOM_UNIFORM: &str = "
+#version 450
+layout(local_size_x = 64) in;
+
+layout(set = 0, binding = 0) buffer Params { uint n; uint m; uint j; uint k; };
+layout(set = 0, binding = 1) buffer Out { float data_out[]; };
+
+// PCG hash, see Jarzynski and Olano, Hash Functions for GPU Rendering
+uint pcg_hash(uint v) {
+    uint state = v * 747796405u + 2891336453u;
+    uint word = ((state >> ((state >> 28u) + 4u)) ^ state) * 277803737u;
+    return (word >> 22u) ^ word;
+}
+
+void main() {
+    uint i = gl_GlobalInvocationID.y * gl_NumWorkGroups.x * 64 + gl_GlobalInvocationID.x;
+    if (i >= n) {
+        return;
+    }
+    uint key = pcg_hash(j ^ pcg_hash(k));
+    uint x = pcg_hash(pcg_hash(i) ^ key);
+    // 24 random bits map exactly onto floats in [0, 1)
+    data_out[i] = float(x >> 8) * (1.0 / 16777216.0);
+}";
+
+/// Copies `keys`/`values` into power-of-two sized buffers, padding with the largest key. This
+/// and the other kernels of `reduce_by_key` are dispatched in rows, see `Device::workgroup_rows`
+const PAD_PAIRS: &str = "
+#version 450
+layout(local_size_x = 64) in;
+
+layout(set = 0, binding = 0) buffer Params { uint n; uint m; uint j; uint k; };
+layout(set = 0, binding = 1) buffer InKeys { uint in_keys[]; };
+layout(set = 0, binding = 2) buffer InValues { float in_values[]; };
+layout(set = 0, binding = 3) buffer Keys { uint keys[]; };
+layout(set = 0, binding = 4) buffer Values { float values[]; };
+
+void main() {
+    uint i = gl_GlobalInvocationID.y * gl_NumWorkGroups.x * 64 + gl_GlobalInvocationID.x;
+    if (i >= m) {
+        return;
+    }
+    keys[i] = i < n ? in_keys[i] : 0xffffffffu;
+    values[i] = i < n ? in_values[i] : 0.0;
+}";
+
+/// One compare-and-swap step `(k, j)` of a bitonic sort of key/value pairs
+const BITONIC_STEP: &str = "
+#version 450
+layout(local_size_x = 64) in;
+
+layout(set = 0, binding = 0) buffer Params { uint n; uint m; uint j; uint k; };
+layout(set = 0, binding = 1) buffer Keys { uint keys[]; };
+layout(set = 0, binding = 2) buffer Values { float values[]; };
+
+void main() {
+    uint i = gl_GlobalInvocationID.y * gl_NumWorkGroups.x * 64 + gl_GlobalInvocationID.x;
+    uint l = i ^ j;
+    if (i >= m || l <= i) {
+        return;
+    }
+    bool ascending = (i & k) == 0;
+    uint ki = keys[i];
+    uint kl = keys[l];
+    if (ki != kl && (ki > kl) == ascending) {
+        keys[i] = kl;
+        keys[l] = ki;
+        float v = values[i];
+        values[i] = values[l];
+        values[l] = v;
+    }
+}";
+
+/// Flags the first element of every run of equal keys
+const SEGMENT_HEADS: &str = "
+#version 450
+layout(local_size_x = 64) in;
+
+layout(set = 0, binding = 0) buffer Params { uint n; uint m; uint j; uint k; };
+layout(set = 0, binding = 1) buffer Keys { uint keys[]; };
+layout(set = 0, binding = 2) buffer Flags { uint flags[]; };
+
+void main() {
+    uint i = gl_GlobalInvocationID.y * gl_NumWorkGroups.x * 64 + gl_GlobalInvocationID.x;
+    if (i >= m) {
+        return;
+    }
+    flags[i] = (i < n && (i == 0 || keys[i] != keys[i - 1])) ? 1u : 0u;
+}";
+
+/// Elements scanned by one workgroup of the block scan, two per invocation
+const SCAN_BLOCK_SIZE: u32 = 512;
+
+/// Work-efficient (Blelloch) scan of one block of 512 elements in shared memory, writing
+/// the total of the block to `block_sums`. `j` selects an inclusive scan. The blocks are
+/// dispatched in rows of workgroups, see `Device::workgroup_rows`.
+const SCAN_BLOCK: &str = "
+#version 450
+layout(local_size_x = 256) in;
+
+layout(set = 0, binding = 0) buffer Params { uint n; uint m; uint j; uint k; };
+layout(set = 0, binding = 1) buffer In { T data_in[]; };
+layout(set = 0, binding = 2) buffer Out { T data_out[]; };
+layout(set = 0, binding = 3) buffer Sums { T block_sums[]; };
+
+shared T temp[512];
+
+void main() {
+    uint local = gl_LocalInvocationID.x;
+    uint group = gl_WorkGroupID.y * gl_NumWorkGroups.x + gl_WorkGroupID.x;
+    uint a = group * 512 + 2 * local;
+    uint b = a + 1;
+    T va = a < n ? data_in[a] : T(0);
+    T vb = b < n ? data_in[b] : T(0);
+    temp[2 * local] = va;
+    temp[2 * local + 1] = vb;
+
+    // Up-sweep: build a tree of partial sums in place
+    uint offset = 1;
+    for (uint d = 256; d > 0; d >>= 1) {
+        barrier();
+        if (local < d) {
+            temp[offset * (2 * local + 2) - 1] += temp[offset * (2 * local + 1) - 1];
+        }
+        offset *= 2;
+    }
+    if (local == 0) {
+        block_sums[group] = temp[511];
+        temp[511] = T(0);
+    }
+
+    // Down-sweep: push the partial sums back down the tree
+    for (uint d = 1; d < 512; d *= 2) {
+        offset >>= 1;
+        barrier();
+        if (local < d) {
+            uint ai = offset * (2 * local + 1) - 1;
+            uint bi = offset * (2 * local + 2) - 1;
+            T t = temp[ai];
+            temp[ai] = temp[bi];
+            temp[bi] += t;
+        }
+    }
+    barrier();
+
+    if (a < n) {
+        data_out[a] = j != 0 ? temp[2 * local] + va : temp[2 * local];
+    }
+    if (b < n) {
+        data_out[b] = j != 0 ? temp[2 * local + 1] + vb : temp[2 * local + 1];
+    }
+}";
+
+/// Adds the scanned total of all earlier blocks to every element of a block scan, dispatched
+/// in rows like `SCAN_BLOCK`
+const ADD_BLOCK_SUMS: &str = "
+#version 450
+layout(local_size_x = 64) in;
+
+layout(set = 0, binding = 0) buffer Params { uint n; uint m; uint j; uint k; };
+layout(set = 0, binding = 1) buffer Out { T data_out[]; };
+layout(set = 0, binding = 2) buffer Offsets { T offsets[]; };
+
+void main() {
+    uint i = gl_GlobalInvocationID.y * gl_NumWorkGroups.x * 64 + gl_GlobalInvocationID.x;
+    if (i >= n) {
+        return;
+    }
+    data_out[i] += offsets[i / 512];
+}";
+
+/// One step of an inclusive segmented scan of the values with stride `j`: elements only
+/// combine with earlier ones of the same segment, so after `log2(n)` steps the last element
+/// of every run holds the reduction of the whole run
+const SEGMENT_SCAN_STEP: &str = "
+#version 450
+layout(local_size_x = 64) in;
+
+layout(set = 0, binding = 0) buffer Params { uint n; uint m; uint j; uint k; };
+layout(set = 0, binding = 1) buffer Segments { uint segments[]; };
+layout(set = 0, binding = 2) buffer In { float data_in[]; };
+layout(set = 0, binding = 3) buffer Out { float data_out[]; };
+
+void main() {
+    uint i = gl_GlobalInvocationID.y * gl_NumWorkGroups.x * 64 + gl_GlobalInvocationID.x;
+    if (i >= n) {
+        return;
+    }
+    bool same = i >= j && segments[i - j] == segments[i];
+    data_out[i] = same ? OP(data_in[i - j], data_in[i]) : data_in[i];
+}";
+
+/// Writes the key and reduced value of every run from its last element into the slot given
+/// by the scanned head flags
+const SEGMENT_TAILS: &str = "
+#version 450
+layout(local_size_x = 64) in;
+
+layout(set = 0, binding = 0) buffer Params { uint n; uint m; uint j; uint k; };
+layout(set = 0, binding = 1) buffer Keys { uint keys[]; };
+layout(set = 0, binding = 2) buffer Segments { uint segments[]; };
+layout(set = 0, binding = 3) buffer Reduced { float reduced[]; };
+layout(set = 0, binding = 4) buffer OutKeys { uint out_keys[]; };
+layout(set = 0, binding = 5) buffer OutValues { float out_values[]; };
+
+void main() {
+    uint i = gl_GlobalInvocationID.y * gl_NumWorkGroups.x * 64 + gl_GlobalInvocationID.x;
+    if (i >= n || (i + 1 < n && segments[i + 1] == segments[i])) {
+        return;
+    }
+    uint segment = segments[i] - 1;
+    out_keys[segment] = keys[i];
+    out_values[segment] = reduced[i];
+}";
+
+/// Inserts `#define`s right after the `#version` line of `source`
+fn with_defines(source: &str, defines: &[(&str, &str)]) -> String {
+    let mut header = String::from("#version 450\n");
+    for (name, value) in defines {
+        header += &format!("#define {} {}\n", name, value);
+    }
+    source.replacen("#version 450\n", &header, 1)
+}
+
+/// Compiles `source` on its first use and reuses the pipeline for the lifetime of the device
+fn compile_kernel(
+    device: &mut Device,
+    source: &str,
+    layout: &GPUSetGroupLayout,
+) -> Result<GPUCompute, CallError> {
+    if let Some(compute) = device.ops_pipelines.get(source) {
+        return Ok(compute.clone());
+    }
+    let shader = GLSLCompile::new(source)
+        .compile("main")
+        .map_err(|error| CallError::Compile(CompileError::InvalidGlsl(error)))?;
+    let compute = device
+        .compile("main", &shader, layout)
+        .map_err(CallError::Compile)?;
+    device
+        .ops_pipelines
+        .insert(source.to_string(), compute.clone());
+    Ok(compute)
+}
+
+impl Device {
+    ///
+    /// Reduces all elements of `gpu` to one value with `op`, in passes that each shrink the
+    /// data by a factor of 256 until a single value is left. An empty buffer reduces to the
+    /// identity of `op`.
+    ///
+    /// If `progress` is given it is called after every pass with the pass number and the
+    /// partial results of that pass, e.g. to display convergence. This reads every
+    /// intermediate buffer back to the host and so synchronizes after each pass, leave it
+    /// as `None` unless the intermediate results are needed.
+    ///
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn reduce<T: GpuScalar>(
+        &mut self,
+        gpu: &GPUData<[T]>,
+        op: ReduceOp,
+        progress: Option<&mut ReduceProgress<T>>,
+    ) -> Result<T, CallError> {
+        if gpu.is_empty() {
+            return Ok(T::identity_value(op));
+        }
+        let layout = ParamsBuilder::new()
+            .param::<u32>(None)
+            .param::<T>(None)
+            .param::<T>(None)
+            .build(Some(0));
+        let source = with_defines(
+            REDUCE_PASS,
+            &[
+                ("T", T::GLSL_TYPE),
+                ("OP(a, b)", op.glsl()),
+                ("IDENTITY", T::identity(op)),
+            ],
+        );
+        let compute = compile_kernel(self, &source, &layout.0)?;
+        self.reduce_passes(&compute, gpu, progress)
+    }
+
+    /// Compares two buffers on the device and only reads back a single flag, which is much
+    /// cheaper than reading both buffers back for large data. Buffers of different sizes
+    /// are never equal.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn buffers_equal<T: bytemuck::Pod>(&mut self, a: &GPUData<[T]>, b: &GPUData<[T]>) -> bool {
+        if a.size != b.size {
+            return false;
+        }
+        let words = (a.padded_size() / 4) as u32;
+        if words == 0 {
+            return true;
+        }
+
+        let params = self.to_device(&[words, 0, 0, 0]);
+        let mismatch = self.to_device(&[0u32]);
+        let args = ParamsBuilder::new()
+            .param(Some(&params))
+            .param(Some(a))
+            .param(Some(b))
+            .param(Some(&mismatch))
+            .build(Some(0));
+        let compute = compile_kernel(self, COMPARE_WORDS, &args.0).unwrap();
+        let workspace = self
+            .workgroup_rows(workgroup_count(words, LOCAL_SIZE))
+            .unwrap();
+        self.submit_compute(&compute, workspace, &args.1, &[])
+            .unwrap();
+
+        block_on(self.get(&mismatch)).unwrap()[0] == 0
+    }
+
+    /// A new buffer holding `[0, 1, ..., n - 1]`, written on the device without any upload
+    pub fn iota(&mut self, n: usize) -> GPUData<[u32]> {
+        let output = self.alloc::<u32>(n);
+        if n == 0 {
+            return output;
+        }
+        {
+            let args = ParamsBuilder::new().param(Some(&output)).build(Some(0));
+            let compute = compile_kernel(self, IOTA, &args.0).unwrap();
+            self.submit_compute(
+                &compute,
+                (workgroup_count(n as u32, LOCAL_SIZE), 1, 1),
+                &args.1,
+                &[],
+            )
+            .unwrap();
+        }
+        output
+    }
+
+    /// Fills a new buffer of `len` uniform random values in `[0, 1)`. The generator is
+    /// counter-based, so the same `seed` always produces the same values.
+    pub fn random(&mut self, len: usize, seed: u64) -> GPUData<[f32]> {
+        let n = len as u32;
+        let params = self.to_device(&[n, 0, seed as u32, (seed >> 32) as u32]);
+        let output = self.alloc::<f32>(len);
+        if len == 0 {
+            return output;
+        }
+        {
+            let args = ParamsBuilder::new()
+                .param(Some(&params))
+                .param(Some(&output))
+                .build(Some(0));
+            let compute = compile_kernel(self, RANDOM_UNIFORM, &args.0).unwrap();
+            let workspace = self.workgroup_rows(workgroup_count(n, LOCAL_SIZE)).unwrap();
+            self.submit_compute(&compute, workspace, &args.1, &[])
+                .unwrap();
+        }
+        output
+    }
+
+    ///
+    /// Prefix sums of `gpu` into a new buffer, exclusive (`[1, 2, 3, 4]` to `[0, 1, 3, 6]`)
+    /// or `inclusive` (to `[1, 3, 6, 10]`).
+    ///
+    /// Each workgroup scans a block of 512 elements with a work-efficient Blelloch scan and
+    /// writes out the block's total. The totals are scanned the same way, recursively, and
+    /// added back onto the blocks, so `n` elements take `log512(n)` levels. All levels share
+    /// one parameter buffer, rewritten before each of their submissions.
+    ///
+    pub fn scan<T: GpuScalar>(
+        &mut self,
+        gpu: &GPUData<[T]>,
+        inclusive: bool,
+    ) -> Result<GPUData<[T]>, CallError> {
+        let n = gpu.len();
+        let output = self.alloc::<T>(n);
+        if n == 0 {
+            return Ok(output);
+        }
+        let block_layout = ParamsBuilder::new()
+            .param::<u32>(None)
+            .param::<T>(None)
+            .param::<T>(None)
+            .param::<T>(None)
+            .build(Some(0));
+        let add_layout = ParamsBuilder::new()
+            .param::<u32>(None)
+            .param::<T>(None)
+            .param::<T>(None)
+            .build(Some(0));
+        let defines = [("T", T::GLSL_TYPE)];
+        let block = compile_kernel(self, &with_defines(SCAN_BLOCK, &defines), &block_layout.0)?;
+        let add = compile_kernel(self, &with_defines(ADD_BLOCK_SUMS, &defines), &add_layout.0)?;
+        let params = self.to_device(&[0u32; 4]);
+        self.scan_level(&block, &add, &params, gpu, &output, n as u32, inclusive)?;
+        Ok(output)
+    }
+
+    /// Scans the first `n` elements of `input` into `output` one block at a time, then adds
+    /// the exclusive scan of the block totals, itself computed by recursing
+    #[allow(clippy::too_many_arguments)]
+    fn scan_level<T: GpuScalar>(
+        &mut self,
+        block: &GPUCompute,
+        add: &GPUCompute,
+        params: &GPUData<[u32]>,
+        input: &GPUData<[T]>,
+        output: &GPUData<[T]>,
+        n: u32,
+        inclusive: bool,
+    ) -> Result<(), CallError> {
+        let groups = workgroup_count(n, SCAN_BLOCK_SIZE);
+        let sums = self.alloc::<T>(groups as usize);
+        {
+            let args = ParamsBuilder::new()
+                .param(Some(params))
+                .param(Some(input))
+                .param(Some(output))
+                .param(Some(&sums))
+                .build(Some(0));
+            self.write_params(params, [n, 0, inclusive as u32, 0]);
+            let workspace = self.workgroup_rows(groups)?;
+            self.submit_compute(block, workspace, &args.1, &[])?;
+        }
+        if groups == 1 {
+            return Ok(());
+        }
+
+        let offsets = self.alloc::<T>(groups as usize);
+        self.scan_level(block, add, params, &sums, &offsets, groups, false)?;
+        let args = ParamsBuilder::new()
+            .param(Some(params))
+            .param(Some(output))
+            .param(Some(&offsets))
+            .build(Some(0));
+        // The deeper levels overwrote the parameters of this one
+        self.write_params(params, [n, 0, inclusive as u32, 0]);
+        let workspace = self.workgroup_rows(workgroup_count(n, LOCAL_SIZE))?;
+        self.submit_compute(add, workspace, &args.1, &[])?;
+        Ok(())
+    }
+
+    ///
+    /// Segmented reduction: combines the `values` sharing the same key with `op` and returns
+    /// the unique keys in ascending order along with their reduced values.
+    ///
+    /// The pairs are bitonic sorted by key on the device and runs of equal keys are numbered
+    /// with `scan`. Every run is then reduced in parallel by a segmented scan of `log2(n)`
+    /// passes, so long runs don't serialize on one invocation. `u32::MAX` is used to pad the
+    /// sort and can't be used as a key.
+    ///
+    /// Fails with `CallError::LengthMismatch` if `keys` and `values` don't hold the same number
+    /// of elements.
+    ///
+    #[cfg(not(target_arch = "wasm32"))]
+    #[allow(clippy::type_complexity)]
+    pub fn reduce_by_key(
+        &mut self,
+        keys: &GPUData<[u32]>,
+        values: &GPUData<[f32]>,
+        op: ReduceOp,
+    ) -> Result<(GPUData<[u32]>, GPUData<[f32]>), CallError> {
+        if keys.len() != values.len() {
+            return Err(CallError::LengthMismatch {
+                expected: keys.len(),
+                found: values.len(),
+            });
+        }
+        if keys.is_empty() {
+            return Ok((self.alloc(0), self.alloc(0)));
+        }
+        let n = keys.len() as u32;
+        let m = n.next_power_of_two();
+        let sort_groups = self.workgroup_rows(workgroup_count(m, LOCAL_SIZE))?;
+        let groups = self.workgroup_rows(workgroup_count(n, LOCAL_SIZE))?;
+
+        let params = self.to_device(&[n, m, 0, 0]);
+        let sort_keys = self.alloc::<u32>(m as usize);
+        let sort_values = self.alloc::<f32>(m as usize);
+
+        let pad = ParamsBuilder::new()
+            .param(Some(&params))
+            .param(Some(keys))
+            .param(Some(values))
+            .param(Some(&sort_keys))
+            .param(Some(&sort_values))
+            .build(Some(0));
+        let compute = compile_kernel(self, PAD_PAIRS, &pad.0)?;
+        self.submit_compute(&compute, sort_groups, &pad.1, &[])?;
+
+        let sort = ParamsBuilder::new()
+            .param(Some(&params))
+            .param(Some(&sort_keys))
+            .param(Some(&sort_values))
+            .build(Some(0));
+        let compute = compile_kernel(self, BITONIC_STEP, &sort.0)?;
+        let mut k = 2;
+        while k <= m {
+            let mut j = k / 2;
+            while j > 0 {
+                self.write_params(&params, [n, m, j, k]);
+                self.submit_compute(&compute, sort_groups, &sort.1, &[])?;
+                j /= 2;
+            }
+            k *= 2;
+        }
+
+        let flags = self.alloc::<u32>(m as usize);
+        {
+            let heads = ParamsBuilder::new()
+                .param(Some(&params))
+                .param(Some(&sort_keys))
+                .param(Some(&flags))
+                .build(Some(0));
+            let compute = compile_kernel(self, SEGMENT_HEADS, &heads.0)?;
+            self.submit_compute(&compute, sort_groups, &heads.1, &[])?;
+        }
+        // The inclusive scan of the head flags numbers the runs from 1
+        let segments = self.scan(&flags, true)?;
+
+        // Ping-pong the values between the sorted buffer and a scratch one
+        let scratch = self.alloc::<f32>(n as usize);
+        let forward = ParamsBuilder::new()
+            .param(Some(&params))
+            .param(Some(&segments))
+            .param(Some(&sort_values))
+            .param(Some(&scratch))
+            .build(Some(0));
+        let backward = ParamsBuilder::new()
+            .param(Some(&params))
+            .param(Some(&segments))
+            .param(Some(&scratch))
+            .param(Some(&sort_values))
+            .build(Some(0));
+        let compute = compile_kernel(
+            self,
+            &with_defines(SEGMENT_SCAN_STEP, &[("OP(a, b)", op.glsl())]),
+            &forward.0,
+        )?;
+        let mut reduced = &sort_values;
+        let mut stride = 1;
+        while stride < n {
+            self.write_params(&params, [n, m, stride, 0]);
+            if std::ptr::eq(reduced, &sort_values) {
+                self.submit_compute(&compute, groups, &forward.1, &[])?;
+                reduced = &scratch;
+            } else {
+                self.submit_compute(&compute, groups, &backward.1, &[])?;
+                reduced = &sort_values;
+            }
+            stride *= 2;
+        }
+
+        // The number of the last run is the number of unique keys
+        let unique = block_on(self.get_range(&segments, n as usize - 1..n as usize))?[0] as usize;
+        let out_keys = self.alloc::<u32>(unique);
+        let out_values = self.alloc::<f32>(unique);
+        {
+            let tails = ParamsBuilder::new()
+                .param(Some(&params))
+                .param(Some(&sort_keys))
+                .param(Some(&segments))
+                .param(Some(reduced))
+                .param(Some(&out_keys))
+                .param(Some(&out_values))
+                .build(Some(0));
+            let compute = compile_kernel(self, SEGMENT_TAILS, &tails.0)?;
+            self.write_params(&params, [n, m, 0, 0]);
+            self.submit_compute(&compute, groups, &tails.1, &[])?;
+        }
+        Ok((out_keys, out_values))
+    }
+}
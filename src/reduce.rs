@@ -0,0 +1,141 @@
+use crate::device::*;
+use crate::error::*;
+use crate::map::WgslScalar;
+#[cfg(not(target_arch = "wasm32"))]
+use futures::executor::block_on;
+use std::convert::TryFrom;
+
+/// Workgroup size of the reduction kernels, each workgroup reduces this many elements
+pub(crate) const REDUCE_LOCAL_SIZE: u32 = 256;
+
+/// One pass of a tree sum: each workgroup adds its elements into one output. Instantiated
+/// for an element type by prepending an `alias T`.
+const SUM_PASS: &str = "
+@group(0) @binding(0) var<storage, read_write> params: array<u32, 4>;
+@group(0) @binding(1) var<storage, read_write> data_in: array<T>;
+@group(0) @binding(2) var<storage, read_write> data_out: array<T>;
+
+var<workgroup> partial: array<T, 256>;
+
+@compute @workgroup_size(256)
+fn main(
+    @builtin(local_invocation_id) local_id: vec3<u32>,
+    @builtin(workgroup_id) group_id: vec3<u32>,
+    @builtin(num_workgroups) groups: vec3<u32>,
+) {
+    let group = group_id.y * groups.x + group_id.x;
+    let lid = local_id.x;
+    let i = group * 256u + lid;
+    var value = T(0);
+    if (i < params[0]) {
+        value = data_in[i];
+    }
+    partial[lid] = value;
+    workgroupBarrier();
+    for (var stride = 128u; stride > 0u; stride = stride >> 1u) {
+        if (lid < stride) {
+            partial[lid] = partial[lid] + partial[lid + stride];
+        }
+        workgroupBarrier();
+    }
+    if (lid == 0u) {
+        data_out[group] = partial[0];
+    }
+}";
+
+/// Called by multi-pass reductions like `Device::reduce` after every pass, with the pass
+/// number and the partial results of that pass
+pub type ReduceProgress<'a, T> = dyn FnMut(usize, &[T]) + 'a;
+
+/// Number of elements of `gpu` as the `u32` the bundled kernels index with
+pub(crate) fn element_count<T>(gpu: &GPUData<[T]>) -> Result<u32, CallError> {
+    u32::try_from(gpu.len()).map_err(|_| CallError::TooManyElements {
+        len: gpu.len(),
+        max: u32::MAX as usize,
+    })
+}
+
+impl Device {
+    /// Sums all elements of `gpu` on the device with a bundled WGSL kernel, in passes that
+    /// each shrink the data by a factor of 256. Supports `u32`, `i32` and `f32`; an empty
+    /// buffer sums to zero. The pipeline is compiled once per element type and cached.
+    /// ```ignore
+    ///     let data = device.to_device(&[1.0f32, 2.0, 3.0]);
+    ///     assert_eq!(device.sum(&data).unwrap(), 6.0);
+    /// ```
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn sum<T: WgslScalar>(&mut self, gpu: &GPUData<[T]>) -> Result<T, CallError> {
+        if gpu.is_empty() {
+            return Ok(T::zeroed());
+        }
+        let source = format!("alias T = {};\n{}", T::WGSL_TYPE, SUM_PASS);
+        let compute = match self.ops_pipelines.get(&source) {
+            Some(compute) => compute.clone(),
+            None => {
+                let layout = ParamsBuilder::new()
+                    .param::<u32>(None)
+                    .param::<T>(None)
+                    .param::<T>(None)
+                    .build(Some(0));
+                let compute = self
+                    .compile_wgsl("main", &source, &layout.0)
+                    .map_err(CallError::Compile)?;
+                self.ops_pipelines.insert(source, compute.clone());
+                compute
+            }
+        };
+        self.reduce_passes(&compute, gpu, None)
+    }
+
+    ///
+    /// Runs a bundled reduction kernel over the non-empty `gpu` until a single value is left.
+    ///
+    /// The kernel reads the element count from the first `u32` of binding 0, the input from
+    /// binding 1 and writes the result of every workgroup of `REDUCE_LOCAL_SIZE` elements to
+    /// binding 2, with the workgroups laid out by `workgroup_rows`. `progress` is called with
+    /// the partial results after every pass.
+    ///
+    #[cfg(not(target_arch = "wasm32"))]
+    pub(crate) fn reduce_passes<T: bytemuck::Pod>(
+        &mut self,
+        compute: &GPUCompute,
+        gpu: &GPUData<[T]>,
+        mut progress: Option<&mut ReduceProgress<T>>,
+    ) -> Result<T, CallError> {
+        let mut n = element_count(gpu)?;
+        let params = self.to_device(&[n, 0, 0, 0]);
+        let mut partials: Option<GPUData<[T]>> = None;
+        let mut pass = 0;
+        loop {
+            let groups = workgroup_count(n, REDUCE_LOCAL_SIZE);
+            let workspace = self.workgroup_rows(groups)?;
+            let output = self.alloc::<T>(groups as usize);
+            {
+                let input = partials.as_ref().unwrap_or(gpu);
+                let args = ParamsBuilder::new()
+                    .param(Some(&params))
+                    .param(Some(input))
+                    .param(Some(&output))
+                    .build(Some(0));
+                self.write_params(&params, [n, 0, 0, 0]);
+                self.submit_compute(compute, workspace, &args.1, &[])?;
+            }
+            pass += 1;
+            if let Some(callback) = &mut progress {
+                let partial = block_on(self.get(&output))?;
+                callback(pass, &partial[..groups as usize]);
+            }
+            if groups == 1 {
+                return Ok(block_on(self.get(&output))?[0]);
+            }
+            partials = Some(output);
+            n = groups;
+        }
+    }
+
+    /// Overwrites the four `u32` parameters the bundled kernels read from binding 0
+    pub(crate) fn write_params(&mut self, params: &GPUData<[u32]>, values: [u32; 4]) {
+        self.queue
+            .write_buffer(&params.storage_buffer, 0, bytemuck::cast_slice(&values));
+    }
+}
@@ -0,0 +1,321 @@
+///
+/// Produces human-readable SPIR-V assembly for a shader, e.g. the words returned by
+/// `load_shader` or `GLSLCompile::compile`. Useful to check what the shader toolchain
+/// actually produced. Fails if the words are not a valid SPIR-V module.
+///
+#[cfg(feature = "rspirv")]
+pub fn disassemble(spirv: &[u32]) -> Result<String, rspirv::binary::ParseState> {
+    use rspirv::binary::Disassemble;
+    let module = rspirv::dr::load_words(spirv)?;
+    Ok(module.disassemble())
+}
+
+const MAGIC_NUMBER: u32 = 0x0723_0203;
+const HEADER_LEN: usize = 5;
+
+const OP_ENTRY_POINT: u32 = 15;
+const OP_EXECUTION_MODE: u32 = 16;
+const OP_CAPABILITY: u32 = 17;
+const OP_TYPE_INT: u32 = 21;
+const OP_TYPE_FLOAT: u32 = 22;
+const OP_TYPE_STRUCT: u32 = 30;
+const OP_TYPE_POINTER: u32 = 32;
+const OP_CONSTANT_TRUE: u32 = 41;
+const OP_CONSTANT_FALSE: u32 = 42;
+const OP_CONSTANT: u32 = 43;
+const OP_CONSTANT_COMPOSITE: u32 = 44;
+const OP_SPEC_CONSTANT_TRUE: u32 = 48;
+const OP_SPEC_CONSTANT_FALSE: u32 = 49;
+const OP_SPEC_CONSTANT: u32 = 50;
+const OP_SPEC_CONSTANT_COMPOSITE: u32 = 51;
+const OP_VARIABLE: u32 = 59;
+const OP_DECORATE: u32 = 71;
+const OP_MEMBER_DECORATE: u32 = 72;
+const EXECUTION_MODEL_GL_COMPUTE: u32 = 5;
+const EXECUTION_MODE_LOCAL_SIZE: u32 = 17;
+const DECORATION_SPEC_ID: u32 = 1;
+const DECORATION_BUILT_IN: u32 = 11;
+const DECORATION_NON_WRITABLE: u32 = 24;
+const DECORATION_BINDING: u32 = 33;
+const DECORATION_DESCRIPTOR_SET: u32 = 34;
+const BUILT_IN_WORKGROUP_SIZE: u32 = 25;
+const CAPABILITY_FLOAT64: u32 = 10;
+
+/// Iterates over the instructions of a module as (opcode, operands)
+fn instructions<'a>(spirv: &'a [u32]) -> impl Iterator<Item = (u32, &'a [u32])> + 'a {
+    let mut rest: &[u32] = if spirv.len() >= HEADER_LEN && spirv[0] == MAGIC_NUMBER {
+        &spirv[HEADER_LEN..]
+    } else {
+        &[]
+    };
+    std::iter::from_fn(move || {
+        let word_count = (*rest.first()? >> 16) as usize;
+        if word_count == 0 || word_count > rest.len() {
+            return None;
+        }
+        let (inst, tail) = rest.split_at(word_count);
+        rest = tail;
+        Some((inst[0] & 0xffff, &inst[1..]))
+    })
+}
+
+/// Decodes a nul-terminated literal string, returning it and the number of words it used
+fn literal_string(words: &[u32]) -> (String, usize) {
+    let mut bytes = vec![];
+    for (i, word) in words.iter().enumerate() {
+        for b in word.to_le_bytes().iter() {
+            if *b == 0 {
+                return (String::from_utf8_lossy(&bytes).into_owned(), i + 1);
+            }
+            bytes.push(*b);
+        }
+    }
+    (String::from_utf8_lossy(&bytes).into_owned(), words.len())
+}
+
+/// Finds the function id of the compute entry point named `entry`
+fn entry_point_id(spirv: &[u32], entry: &str) -> Option<u32> {
+    instructions(spirv).find_map(|(op, operands)| {
+        if op != OP_ENTRY_POINT || operands.len() < 3 || operands[0] != EXECUTION_MODEL_GL_COMPUTE {
+            return None;
+        }
+        let (name, _) = literal_string(&operands[2..]);
+        if name == entry {
+            Some(operands[1])
+        } else {
+            None
+        }
+    })
+}
+
+///
+/// Reflects the `local_size` declared by the compute entry point `entry`, i.e. the
+/// number of invocations in one workgroup along each dimension.
+///
+/// A `WorkgroupSize` built-in, e.g. from `local_size_x_id`, takes precedence over the
+/// `LocalSize` execution mode; specialization constants are read with their default values.
+///
+pub fn workgroup_size(spirv: &[u32], entry: &str) -> Option<(u32, u32, u32)> {
+    let id = entry_point_id(spirv, entry)?;
+    if let Some(size) = workgroup_size_builtin(spirv) {
+        return Some(size);
+    }
+    instructions(spirv).find_map(|(op, operands)| match operands {
+        [target, EXECUTION_MODE_LOCAL_SIZE, x, y, z, ..]
+            if op == OP_EXECUTION_MODE && *target == id =>
+        {
+            Some((*x, *y, *z))
+        }
+        _ => None,
+    })
+}
+
+///
+/// Lists the `(set, binding)` pairs of the resources a module declares, sorted, e.g.
+/// `(0, 1)` for `layout(set = 0, binding = 1) buffer`. Declarations without a `set`
+/// qualifier are in set 0.
+///
+pub fn bindings(spirv: &[u32]) -> Vec<(u32, u32)> {
+    let mut declared: Vec<(u32, u32)> = binding_variables(spirv).into_values().collect();
+    declared.sort();
+    declared
+}
+
+///
+/// Lists the `(set, binding)` pairs of the buffers a module declares `readonly`, sorted. A
+/// buffer is read-only if its variable is decorated `NonWritable`, or every member of its
+/// block is, which is how glslang translates a `readonly buffer`.
+///
+pub fn readonly_bindings(spirv: &[u32]) -> Vec<(u32, u32)> {
+    let mut non_writable = std::collections::HashSet::new();
+    let mut non_writable_members = std::collections::HashMap::new();
+    let mut member_counts = std::collections::HashMap::new();
+    let mut pointees = std::collections::HashMap::new();
+    let mut variable_types = std::collections::HashMap::new();
+    for (op, operands) in instructions(spirv) {
+        match operands {
+            [target, DECORATION_NON_WRITABLE] if op == OP_DECORATE => {
+                non_writable.insert(*target);
+            }
+            [target, _, DECORATION_NON_WRITABLE] if op == OP_MEMBER_DECORATE => {
+                *non_writable_members.entry(*target).or_insert(0) += 1;
+            }
+            [result, members @ ..] if op == OP_TYPE_STRUCT => {
+                member_counts.insert(*result, members.len());
+            }
+            [result, _, pointee] if op == OP_TYPE_POINTER => {
+                pointees.insert(*result, *pointee);
+            }
+            [result_type, result, ..] if op == OP_VARIABLE => {
+                variable_types.insert(*result, *result_type);
+            }
+            _ => {}
+        }
+    }
+    let mut readonly: Vec<(u32, u32)> = binding_variables(spirv)
+        .into_iter()
+        .filter(|(variable, _)| {
+            if non_writable.contains(variable) {
+                return true;
+            }
+            let block = match variable_types.get(variable).and_then(|ty| pointees.get(ty)) {
+                Some(block) => block,
+                None => return false,
+            };
+            let members = member_counts.get(block).copied().unwrap_or(0);
+            members > 0 && non_writable_members.get(block) == Some(&members)
+        })
+        .map(|(_, binding)| binding)
+        .collect();
+    readonly.sort();
+    readonly
+}
+
+/// Maps the id of every variable decorated with a binding to its `(set, binding)`
+fn binding_variables(spirv: &[u32]) -> std::collections::HashMap<u32, (u32, u32)> {
+    let mut sets = std::collections::HashMap::new();
+    let mut bindings = std::collections::HashMap::new();
+    for (op, operands) in instructions(spirv) {
+        match operands {
+            [target, DECORATION_DESCRIPTOR_SET, set] if op == OP_DECORATE => {
+                sets.insert(*target, *set);
+            }
+            [target, DECORATION_BINDING, binding] if op == OP_DECORATE => {
+                bindings.insert(*target, *binding);
+            }
+            _ => {}
+        }
+    }
+    bindings
+        .into_iter()
+        .map(|(target, binding)| (target, (*sets.get(&target).unwrap_or(&0), binding)))
+        .collect()
+}
+
+///
+/// Whether the module declares the `Float64` capability, i.e. uses `double`, which needs a
+/// device created with `Features::SHADER_F64`.
+///
+pub fn uses_float64(spirv: &[u32]) -> bool {
+    instructions(spirv)
+        .any(|(op, operands)| op == OP_CAPABILITY && operands == [CAPABILITY_FLOAT64])
+}
+
+/// Reads the constant decorated as the `WorkgroupSize` built-in, if the module has one
+fn workgroup_size_builtin(spirv: &[u32]) -> Option<(u32, u32, u32)> {
+    let builtin = instructions(spirv).find_map(|(op, operands)| match operands {
+        [target, DECORATION_BUILT_IN, BUILT_IN_WORKGROUP_SIZE] if op == OP_DECORATE => {
+            Some(*target)
+        }
+        _ => None,
+    })?;
+    let scalar = |id: u32| {
+        instructions(spirv).find_map(|(op, operands)| match operands {
+            [_, result, value, ..]
+                if (op == OP_CONSTANT || op == OP_SPEC_CONSTANT) && *result == id =>
+            {
+                Some(*value)
+            }
+            _ => None,
+        })
+    };
+    instructions(spirv).find_map(|(op, operands)| match operands {
+        [_, result, x, y, z]
+            if (op == OP_CONSTANT_COMPOSITE || op == OP_SPEC_CONSTANT_COMPOSITE)
+                && *result == builtin =>
+        {
+            Some((scalar(*x)?, scalar(*y)?, scalar(*z)?))
+        }
+        _ => None,
+    })
+}
+
+///
+/// Freezes the specialization constants of a module, e.g. those declared with `constant_id` or
+/// `local_size_x_id` in GLSL. Each `(spec_id, value)` in `constants` overrides the default of
+/// the constant with that `SpecId`, converted to the type the constant is declared with the
+/// way `as` does, e.g. `2.7` becomes `2` for an `int` constant; booleans are true for any
+/// non-zero value. Every scalar and composite specialization constant becomes a regular
+/// constant, so reflection such as `workgroup_size` sees the specialized values.
+///
+pub fn specialize(spirv: &[u32], constants: &[(u32, f64)]) -> Vec<u32> {
+    // (is float, is signed, width) of every scalar number type
+    let types: std::collections::HashMap<u32, (bool, bool, u32)> = instructions(spirv)
+        .filter_map(|(op, operands)| match (op, operands) {
+            (OP_TYPE_INT, [result, width, signed]) => {
+                Some((*result, (false, *signed != 0, *width)))
+            }
+            (OP_TYPE_FLOAT, [result, width]) => Some((*result, (true, true, *width))),
+            _ => None,
+        })
+        .collect();
+    freeze_spec_constants(spirv, |spec_id, result_type| {
+        let value = constants.iter().find(|(s, _)| *s == spec_id)?.1;
+        let words = match types.get(&result_type) {
+            Some((true, _, 64)) => split_u64(value.to_bits()),
+            Some((true, _, _)) => vec![(value as f32).to_bits()],
+            Some((false, true, 64)) => split_u64(value as i64 as u64),
+            Some((false, false, 64)) => split_u64(value as u64),
+            Some((false, true, _)) => vec![value as i32 as u32],
+            Some((false, false, _)) => vec![value as u32],
+            // Booleans
+            None => vec![(value != 0.0) as u32],
+        };
+        Some(words)
+    })
+}
+
+/// Low word first, as SPIR-V stores 64-bit literals
+fn split_u64(value: u64) -> Vec<u32> {
+    vec![value as u32, (value >> 32) as u32]
+}
+
+/// Turns every specialization constant into a regular constant, with the value words returned
+/// by `value_of(spec_id, result_type)` or its default for `None`, and drops the
+/// `SpecId` decorations
+fn freeze_spec_constants<F>(spirv: &[u32], value_of: F) -> Vec<u32>
+where
+    F: Fn(u32, u32) -> Option<Vec<u32>>,
+{
+    let spec_ids: std::collections::HashMap<u32, u32> = instructions(spirv)
+        .filter_map(|(op, operands)| match operands {
+            [target, DECORATION_SPEC_ID, spec_id] if op == OP_DECORATE => Some((*target, *spec_id)),
+            _ => None,
+        })
+        .collect();
+    let value_of = |result_type: u32, id: u32| value_of(*spec_ids.get(&id)?, result_type);
+
+    if spirv.len() < HEADER_LEN || spirv[0] != MAGIC_NUMBER {
+        return spirv.to_vec();
+    }
+    let mut out = spirv[..HEADER_LEN].to_vec();
+    for (op, operands) in instructions(spirv) {
+        let mut operands = operands.to_vec();
+        let op = match op {
+            OP_DECORATE if operands.len() == 3 && operands[1] == DECORATION_SPEC_ID => continue,
+            OP_SPEC_CONSTANT => {
+                // Replaces the low words first, so a 32-bit value keeps the high word of a
+                // 64-bit default
+                let value = value_of(operands[0], operands[1]).unwrap_or_default();
+                for (word, new) in operands[2..].iter_mut().zip(value) {
+                    *word = new;
+                }
+                OP_CONSTANT
+            }
+            OP_SPEC_CONSTANT_TRUE | OP_SPEC_CONSTANT_FALSE => {
+                match value_of(operands[0], operands[1]) {
+                    Some(value) if value.iter().all(|word| *word == 0) => OP_CONSTANT_FALSE,
+                    Some(_) => OP_CONSTANT_TRUE,
+                    None if op == OP_SPEC_CONSTANT_TRUE => OP_CONSTANT_TRUE,
+                    None => OP_CONSTANT_FALSE,
+                }
+            }
+            OP_SPEC_CONSTANT_COMPOSITE => OP_CONSTANT_COMPOSITE,
+            op => op,
+        };
+        let word_count = operands.len() as u32 + 1;
+        out.push(word_count << 16 | op);
+        out.extend_from_slice(&operands);
+    }
+    out
+}
@@ -0,0 +1,185 @@
+use crate::device::*;
+
+///
+/// A 2D storage texture on the device, which a compute shader can read and write
+/// through an `image2D` binding.
+///
+pub struct GPUTexture {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub format: wgpu::TextureFormat,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl GPUTexture {
+    #[cfg(feature = "image")]
+    fn extent(&self) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width: self.width,
+            height: self.height,
+            depth_or_array_layers: 1,
+        }
+    }
+}
+
+impl Device {
+    pub fn create_texture(
+        &mut self,
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+    ) -> GPUTexture {
+        let label = self.resource_label("Texture");
+        let texture = self.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(&label),
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::STORAGE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        GPUTexture {
+            texture,
+            view,
+            format,
+            width,
+            height,
+        }
+    }
+
+    /// Uploads an image into a new `Rgba8Unorm` storage texture
+    #[cfg(feature = "image")]
+    pub fn image_to_texture(&mut self, image: &image::RgbaImage) -> GPUTexture {
+        let (width, height) = image.dimensions();
+        let texture = self.create_texture(width, height, wgpu::TextureFormat::Rgba8Unorm);
+        self.queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture: &texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            image.as_raw(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * width),
+                rows_per_image: Some(height),
+            },
+            texture.extent(),
+        );
+        texture
+    }
+
+    /// Reads a storage texture back into an image. `Rgba8Unorm` textures are copied as is
+    /// and `Rgba32Float` textures are clamped to `[0, 1]` and quantized, other formats
+    /// are not supported and return `None`.
+    #[cfg(feature = "image")]
+    pub async fn texture_to_image(&mut self, texture: &GPUTexture) -> Option<image::RgbaImage> {
+        let texel_size = match texture.format {
+            wgpu::TextureFormat::Rgba8Unorm => 4,
+            wgpu::TextureFormat::Rgba32Float => 16,
+            _ => return None,
+        };
+        // Rows of a texture-to-buffer copy must start at multiples of 256 bytes
+        let row_size = (texture.width * texel_size) as u64;
+        let padded_row_size = align_to(row_size, wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as u64);
+
+        let label = self.resource_label("Texture Readback Buffer");
+        let buffer = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some(&label),
+            size: padded_row_size * texture.height as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_texture_to_buffer(
+            wgpu::ImageCopyTexture {
+                texture: &texture.texture,
+                mip_level: 0,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_size as u32),
+                    rows_per_image: Some(texture.height),
+                },
+            },
+            texture.extent(),
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let buffer_slice = buffer.slice(0..);
+        let buffer_future = map_read(buffer_slice);
+        self.device.poll(wgpu::Maintain::Wait);
+        if buffer_future.await.is_err() {
+            return None;
+        }
+
+        let data = buffer_slice.get_mapped_range();
+        let mut pixels = Vec::with_capacity((4 * texture.width * texture.height) as usize);
+        for row in data.chunks(padded_row_size as usize) {
+            let row = &row[..row_size as usize];
+            match texture.format {
+                wgpu::TextureFormat::Rgba32Float => pixels.extend(
+                    row.chunks_exact(4)
+                        .map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+                        .map(|v| (v.max(0.0).min(1.0) * 255.0).round() as u8),
+                ),
+                _ => pixels.extend_from_slice(row),
+            }
+        }
+        drop(data);
+        buffer.unmap();
+
+        image::RgbaImage::from_raw(texture.width, texture.height, pixels)
+    }
+}
+
+impl<'a> ParamsBuilder<'a> {
+    /// Adds a write-enabled storage texture binding, declared in GLSL as e.g.
+    /// `layout(set = 0, binding = 0, rgba8) uniform image2D img;`
+    pub fn texture(mut self, texture: &'a GPUTexture) -> Self {
+        let new_binding_layout_idx = self.next_binding();
+
+        self.binding_layouts.insert(
+            new_binding_layout_idx,
+            (
+                wgpu::BindGroupLayoutEntry {
+                    binding: new_binding_layout_idx,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::StorageTexture {
+                        access: wgpu::StorageTextureAccess::WriteOnly,
+                        format: texture.format,
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                    },
+                    count: None,
+                },
+                String::from("texture"),
+            ),
+        );
+        self.binding_entry.insert(
+            new_binding_layout_idx,
+            wgpu::BindGroupEntry {
+                binding: new_binding_layout_idx,
+                resource: wgpu::BindingResource::TextureView(&texture.view),
+            },
+        );
+        self
+    }
+}
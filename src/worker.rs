@@ -0,0 +1,61 @@
+use crate::device::*;
+use futures::channel::oneshot;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc;
+
+type Job = Box<dyn FnOnce(&mut Device) + Send>;
+
+///
+/// A handle to a `Device` owned by a dedicated thread, see `Device::spawn_worker`.
+///
+/// Jobs are closures run on the worker thread with the device; their results come back through
+/// a oneshot receiver that can be awaited or blocked on. Clones send to the same worker, and the
+/// thread exits once every handle has been dropped and the queued jobs have run.
+/// ```ignore
+///     let worker = device.spawn_worker();
+///     let result = worker.run(move |device| {
+///         let data = device.to_device(input.as_slice());
+///         futures::executor::block_on(device.get(&data)).unwrap()
+///     });
+///     let output = futures::executor::block_on(result).unwrap();
+/// ```
+///
+#[derive(Clone)]
+pub struct DeviceWorker {
+    sender: mpsc::Sender<Job>,
+}
+
+impl DeviceWorker {
+    /// Queues `job` on the worker thread. The receiver fails with `Canceled` if the job panicked,
+    /// the worker itself keeps running the jobs queued after it.
+    pub fn run<F, R>(&self, job: F) -> oneshot::Receiver<R>
+    where
+        F: FnOnce(&mut Device) -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let (tx, rx) = oneshot::channel();
+        let job: Job = Box::new(move |device| {
+            // The caller may have stopped waiting, which is not an error for the worker
+            let _ = tx.send(job(device));
+        });
+        // The worker only stops once all senders are gone, so this cannot fail
+        self.sender.send(job).unwrap();
+        rx
+    }
+}
+
+impl Device {
+    /// Moves the device onto a new thread that runs all GPU work submitted through the returned
+    /// `DeviceWorker`, for applications that confine wgpu calls to a single thread.
+    pub fn spawn_worker(self) -> DeviceWorker {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        std::thread::spawn(move || {
+            let mut device = self;
+            for job in receiver {
+                // A panicking job drops its sender while unwinding, which cancels its receiver
+                let _ = panic::catch_unwind(AssertUnwindSafe(|| job(&mut device)));
+            }
+        });
+        DeviceWorker { sender }
+    }
+}
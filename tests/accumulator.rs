@@ -0,0 +1,43 @@
+#[cfg(feature = "glsl")]
+#[test]
+fn histogram_over_batches() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Values {
+        uint[] values;
+    };
+
+    layout(set = 0, binding = 1) buffer Histogram {
+        uint[] bins;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        atomicAdd(bins[values[index]], 1);
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let mut device = alkomp::Device::new(0).unwrap();
+
+    let hist = alkomp::Accumulator::<u32>::new(&mut device, 4);
+    let layout = alkomp::Accumulator::<u32>::layout::<u32>();
+    let compute = device.compile("main", &shader, &layout).unwrap();
+
+    let batches: Vec<Vec<u32>> = vec![vec![0, 1, 1, 3], vec![3, 3, 2, 0], vec![1, 1, 1, 1]];
+    for batch in &batches {
+        let input = device.to_device(batch.as_slice());
+        hist.add_batch(&mut device, &compute, &input, (batch.len() as u32, 1, 1))
+            .unwrap();
+    }
+
+    let bins = futures::executor::block_on(hist.read(&mut device)).unwrap();
+    assert_eq!(&[2, 6, 1, 3], &bins[..]);
+
+    hist.reset(&mut device);
+    let bins = futures::executor::block_on(hist.read(&mut device)).unwrap();
+    assert_eq!(&[0, 0, 0, 0], &bins[..]);
+}
@@ -1,5 +1,4 @@
-use alkomp;
-#[cfg(feature = "shaderc")]
+#[cfg(feature = "glsl")]
 #[test]
 fn compute_on_device() {
     let code = "
@@ -34,7 +33,7 @@ fn compute_on_device() {
 
     let arr: Vec<u32> = vec![1, 2, 3, 4];
 
-    let mut device = alkomp::Device::new(0);
+    let mut device = alkomp::Device::new(0).unwrap();
     let data_gpu = device.to_device(arr.as_slice());
 
     let args = alkomp::ParamsBuilder::new()
@@ -43,10 +42,2498 @@ fn compute_on_device() {
 
     let compute = device.compile("main", &shader, &args.0).unwrap();
 
-    device.call(compute, (arr.len() as u32, 1, 1), &args.1);
+    device
+        .call(&compute, (arr.len() as u32, 1, 1), &args.1)
+        .unwrap();
 
-    let collatz = futures::executor::block_on(device.get(&data_gpu)).unwrap();
-    let collatz = &collatz[0..collatz.len() - 1];
+    let collatz = device.get_blocking(&data_gpu).unwrap();
 
     assert_eq!(&[0, 1, 7, 2], &collatz[..]);
 }
+
+#[test]
+fn call_checked_reports_validation_errors() {
+    let code = "
+    @group(0) @binding(0) var<storage, read_write> data: array<u32>;
+
+    @compute @workgroup_size(1)
+    fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+        if (id.x < arrayLength(&data)) {
+            data[id.x] = data[id.x] + 1u;
+        }
+    }";
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let data_gpu = device.to_device(&[1u32, 2, 3, 4]);
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&data_gpu))
+        .build(Some(0));
+    let compute = device.compile_wgsl("main", code, &args.0).unwrap();
+
+    device.call_checked(&compute, (4, 1, 1), &args.1).unwrap();
+    assert_eq!(&device.get_blocking(&data_gpu).unwrap()[..], &[2, 3, 4, 5]);
+
+    // More workgroups than any device allows along one dimension
+    match device.call_checked(&compute, (u32::MAX, 1, 1), &args.1) {
+        Err(alkomp::CallError::Validation(_)) => {}
+        Err(error) => panic!("expected a validation error, got {}", error),
+        Ok(_) => panic!("expected a validation error"),
+    }
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn compute_with_kernel_builder() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Input {
+        uint[] a;
+    };
+
+    layout(set = 0, binding = 1) buffer Output {
+        uint[] b;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        b[index] = a[index] * 2;
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let a: Vec<u32> = vec![1, 2, 3, 4];
+    let b: Vec<u32> = vec![0; 4];
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let a_gpu = device.to_device(a.as_slice());
+    let b_gpu = device.to_device(b.as_slice());
+
+    let (compute, args) = device
+        .kernel(&shader)
+        .entry("main")
+        .param(&a_gpu)
+        .param(&b_gpu)
+        .build()
+        .unwrap();
+
+    device.call(&compute, (4, 1, 1), &args).unwrap();
+
+    let b = device.get_blocking(&b_gpu).unwrap();
+    assert_eq!(&[2, 4, 6, 8], &b[..]);
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn read_after_write_is_never_stale() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Counters {
+        uint[] counters;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        counters[index] += 1;
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let arr: Vec<u32> = vec![0; 64];
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let data_gpu = device.to_device(arr.as_slice());
+
+    for i in 1..=200 {
+        let args = alkomp::ParamsBuilder::new()
+            .param(Some(&data_gpu))
+            .build(Some(0));
+        let compute = device.compile("main", &shader, &args.0).unwrap();
+        device
+            .call(&compute, (arr.len() as u32, 1, 1), &args.1)
+            .unwrap();
+
+        let counters = device.get_blocking(&data_gpu).unwrap();
+        assert!(counters[0..arr.len()].iter().all(|c| *c == i));
+    }
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn mixed_precision_f16_in_f32_out() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Halves {
+        uint[] packed;
+    };
+
+    layout(set = 0, binding = 1) buffer Sums {
+        float[] sums;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        vec2 pair = unpackHalf2x16(packed[index]);
+        sums[index] += pair.x + pair.y;
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    // 1.0, 2.0, 0.5, 0.25, 3.0 as f16 bits; the odd length is padded with a zero
+    let halves: Vec<u16> = vec![0x3C00, 0x4000, 0x3800, 0x3400, 0x4200];
+    let sums: Vec<f32> = vec![0.0; 3];
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let halves_gpu = device.to_device(halves.as_slice());
+    let sums_gpu = device.to_device(sums.as_slice());
+
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&halves_gpu))
+        .param(Some(&sums_gpu))
+        .build(Some(0));
+
+    let compute = device.compile("main", &shader, &args.0).unwrap();
+    device
+        .call(&compute, (sums.len() as u32, 1, 1), &args.1)
+        .unwrap();
+
+    let sums = device.get_blocking(&sums_gpu).unwrap();
+    assert_eq!(&[3.0, 0.75, 3.0], &sums[..]);
+}
+
+#[cfg(all(feature = "glsl", feature = "half"))]
+#[test]
+fn f16_round_trip_doubles_exactly() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Halves {
+        uint[] packed;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        packed[index] = packHalf2x16(unpackHalf2x16(packed[index]) * 2.0);
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let values: Vec<f32> = vec![1.0, -0.5, 0.125, 1000.0, 3.25, 0.0];
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let halves_gpu = device.to_device_f16(values.as_slice());
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&halves_gpu))
+        .build(Some(0));
+
+    let compute = device.compile("main", &shader, &args.0).unwrap();
+    device
+        .call(&compute, (values.len() as u32 / 2, 1, 1), &args.1)
+        .unwrap();
+
+    let halves = device.get_blocking(&halves_gpu).unwrap();
+    let expected: Vec<half::f16> = values
+        .iter()
+        .map(|x| half::f16::from_f32(x * 2.0))
+        .collect();
+    assert_eq!(&expected[..], &halves[..]);
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn drop_input_while_in_flight() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Input {
+        uint[] a;
+    };
+
+    layout(set = 0, binding = 1) buffer Output {
+        uint[] b;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        b[index] = a[index] + 1;
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let mut device = alkomp::Device::new(0).unwrap();
+
+    for i in 0..100u32 {
+        let a: Vec<u32> = vec![i; 256];
+        let a_gpu = device.to_device(a.as_slice());
+        let b_gpu = device.to_device(vec![0u32; 256].as_slice());
+        {
+            let args = alkomp::ParamsBuilder::new()
+                .param(Some(&a_gpu))
+                .param(Some(&b_gpu))
+                .build(Some(0));
+            let compute = device.compile("main", &shader, &args.0).unwrap();
+            device.call(&compute, (256, 1, 1), &args.1).unwrap();
+        }
+        // The dispatch may still be running when the input goes away
+        drop(a_gpu);
+
+        let b = device.get_blocking(&b_gpu).unwrap();
+        assert!(b[0..256].iter().all(|x| *x == i + 1));
+    }
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn incremental_readback_updates_dirty_range() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Data {
+        uint[] data;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x + 10;
+        data[index] = 7;
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let arr: Vec<u32> = (0..64).collect();
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let data_gpu = device.to_device(arr.as_slice());
+
+    let mut prev = Vec::new();
+    futures::executor::block_on(device.get_incremental(&data_gpu, 0..0, &mut prev)).unwrap();
+    assert_eq!(&prev[0..64], arr.as_slice());
+
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&data_gpu))
+        .build(Some(0));
+    let compute = device.compile("main", &shader, &args.0).unwrap();
+    device.call(&compute, (5, 1, 1), &args.1).unwrap();
+
+    // Only part of the changed region is marked dirty, the rest keeps its old values
+    futures::executor::block_on(device.get_incremental(&data_gpu, 11..13, &mut prev)).unwrap();
+    for (i, v) in prev[0..64].iter().enumerate() {
+        if i == 11 || i == 12 {
+            assert_eq!(*v, 7);
+        } else {
+            assert_eq!(*v, i as u32);
+        }
+    }
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn fused_matches_separate_calls() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Data {
+        uint[] data;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        data[index] = data[index] * 3 + 1;
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let arr: Vec<u32> = (0..32).collect();
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let fused = device
+        .run_fused(&shader, "main", arr.as_slice(), (arr.len() as u32, 1, 1))
+        .unwrap();
+
+    let expected: Vec<u32> = arr.iter().map(|x| x * 3 + 1).collect();
+    assert_eq!(&fused[0..arr.len()], expected.as_slice());
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn max_inflight_bounds_outstanding_dispatches() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Counters {
+        uint[] counters;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        counters[index] += 1;
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let arr: Vec<u32> = vec![0; 1024];
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    assert_eq!(device.max_inflight(), None);
+    device.set_max_inflight(Some(2));
+
+    let data_gpu = device.to_device(arr.as_slice());
+    for _ in 0..50 {
+        let args = alkomp::ParamsBuilder::new()
+            .param(Some(&data_gpu))
+            .build(Some(0));
+        let compute = device.compile("main", &shader, &args.0).unwrap();
+        device
+            .call(&compute, (arr.len() as u32, 1, 1), &args.1)
+            .unwrap();
+        assert!(device.inflight() <= 2);
+    }
+
+    let counters = device.get_blocking(&data_gpu).unwrap();
+    assert!(counters[0..arr.len()].iter().all(|c| *c == 50));
+    assert_eq!(device.inflight(), 0);
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn specialized_pipelines_are_cached() {
+    let code = "
+    #version 450
+    layout(local_size_x_id = 0) in;
+    layout(constant_id = 1) const uint SCALE = 1;
+
+    layout(set = 0, binding = 0) buffer Data {
+        uint[] data;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        data[index] = data[index] * SCALE;
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let arr: Vec<u32> = (0..64).collect();
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let data_gpu = device.to_device(arr.as_slice());
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&data_gpu))
+        .build(Some(0));
+
+    let compute = device
+        .compile_specialized("main", &shader, &args.0, &[(0, 16.0), (1, 3.0)])
+        .unwrap();
+    assert_eq!(compute.local_size(), Some((16, 1, 1)));
+    device.call(&compute, (4, 1, 1), &args.1).unwrap();
+
+    let again = device
+        .compile_specialized("main", &shader, &args.0, &[(1, 3.0), (0, 16.0)])
+        .unwrap();
+    let other = device
+        .compile_specialized("main", &shader, &args.0, &[(0, 32.0), (1, 3.0)])
+        .unwrap();
+    assert_eq!(other.local_size(), Some((32, 1, 1)));
+    drop((again, other));
+
+    let stats = device.specialization_cache_stats();
+    assert_eq!((stats.hits, stats.misses), (1, 2));
+
+    let data = device.get_blocking(&data_gpu).unwrap();
+    let expected: Vec<u32> = arr.iter().map(|x| x * 3).collect();
+    assert_eq!(&data[0..arr.len()], expected.as_slice());
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn float_constants_are_specialized() {
+    let code = "
+    #version 450
+    layout(local_size_x_id = 0) in;
+    layout(constant_id = 1) const float SCALE = 1.0;
+
+    layout(set = 0, binding = 0) buffer Data {
+        float[] data;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        data[index] = float(index) * SCALE;
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let arr: Vec<f32> = vec![0.0; 16];
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let data_gpu = device.to_device(arr.as_slice());
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&data_gpu))
+        .build(Some(0));
+
+    let compute = device
+        .compile_specialized("main", &shader, &args.0, &[(0, 8.0), (1, 0.5)])
+        .unwrap();
+    assert_eq!(compute.local_size(), Some((8, 1, 1)));
+    device.call(&compute, (2, 1, 1), &args.1).unwrap();
+
+    let data = device.get_blocking(&data_gpu).unwrap();
+    let expected: Vec<f32> = (0..16).map(|i| i as f32 * 0.5).collect();
+    assert_eq!(&expected[..], &data[..]);
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn autotune_picks_a_candidate() {
+    let code = "
+    #version 450
+    layout(local_size_x_id = 0) in;
+
+    layout(set = 0, binding = 0) buffer Data {
+        float[] data;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        if (index < data.length()) {
+            data[index] = sqrt(data[index]) * 2.0;
+        }
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let input: Vec<f32> = (0..1 << 16).map(|i| i as f32).collect();
+    let candidates = [32, 64, 128, 256];
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let best = device
+        .autotune(&shader, "main", input.as_slice(), &candidates)
+        .unwrap();
+
+    assert!(candidates.contains(&best));
+    assert_eq!(device.specialization_cache_stats().misses, 4);
+
+    match device.autotune(&shader, "main", input.as_slice(), &[]) {
+        Err(alkomp::CallError::NoCandidates) => {}
+        _ => panic!("expected no candidates"),
+    }
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn drop_device_with_work_inflight() {
+    let code = "
+    #version 450
+    layout(local_size_x = 64) in;
+
+    layout(set = 0, binding = 0) buffer Data {
+        float[] data;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        for (int i = 0; i < 1000; i++) {
+            data[index] = sin(data[index]) + 1.0;
+        }
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let arr: Vec<f32> = vec![0.0; 1 << 16];
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let data_gpu = device.to_device(arr.as_slice());
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&data_gpu))
+        .build(Some(0));
+    let compute = device.compile("main", &shader, &args.0).unwrap();
+    for _ in 0..10 {
+        device.call(&compute, (1 << 10, 1, 1), &args.1).unwrap();
+    }
+    drop(args);
+    drop(data_gpu);
+    drop(device);
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn dispatch_u64_tiles_with_base_offsets() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(push_constant) uniform Tile {
+        uvec2 base;
+        uint count;
+    };
+
+    layout(set = 0, binding = 0) buffer Data {
+        uint[] data;
+    };
+
+    void main() {
+        if (gl_GlobalInvocationID.x >= count) {
+            return;
+        }
+        // The buffer is small enough that the high word of the base is always 0
+        uint index = base.x + gl_GlobalInvocationID.x;
+        data[index] = index * 2;
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let mut device = alkomp::Device::new(0).unwrap();
+
+    // More invocations than one dispatch of single-invocation workgroups can cover
+    let max = device.device.limits().max_compute_workgroups_per_dimension;
+    let total = max as usize + 1000;
+    let arr: Vec<u32> = vec![0; total];
+
+    let data_gpu = device.to_device(arr.as_slice());
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&data_gpu))
+        .build(Some(0));
+    let compute = device.compile_with_push_constants("main", &shader, &args.0, 12);
+    if !device
+        .device
+        .features()
+        .contains(wgpu::Features::PUSH_CONSTANTS)
+    {
+        assert!(compute.is_err());
+        return;
+    }
+    let compute = compute.unwrap();
+
+    match device.dispatch_u64(&compute, total as u64, 0, &args.1) {
+        Err(alkomp::CallError::ZeroLocalSize) => {}
+        _ => panic!("expected a zero local size to be rejected"),
+    }
+    device
+        .dispatch_u64(&compute, total as u64, 1, &args.1)
+        .unwrap();
+
+    let data = device.get_blocking(&data_gpu).unwrap();
+    assert!(data[0..total]
+        .iter()
+        .enumerate()
+        .all(|(i, x)| *x == i as u32 * 2));
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn call_with_push_constants_sets_the_range() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(push_constant) uniform Params {
+        uint iteration;
+    };
+
+    layout(set = 0, binding = 0) buffer Data {
+        uint[] data;
+    };
+
+    void main() {
+        data[gl_GlobalInvocationID.x] += iteration;
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let arr: Vec<u32> = vec![0; 8];
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let data_gpu = device.to_device(arr.as_slice());
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&data_gpu))
+        .build(Some(0));
+    let compute = device.compile_with_push_constants("main", &shader, &args.0, 4);
+    if !device
+        .device
+        .features()
+        .contains(wgpu::Features::PUSH_CONSTANTS)
+    {
+        match compute {
+            Err(alkomp::CompileError::MissingFeature(feature)) => {
+                assert_eq!(feature, wgpu::Features::PUSH_CONSTANTS)
+            }
+            _ => panic!("expected push constants to need PUSH_CONSTANTS"),
+        }
+        return;
+    }
+    let compute = compute.unwrap();
+
+    for iteration in 1..4u32 {
+        device
+            .call_with_push_constants(&compute, (8, 1, 1), &args.1, &iteration.to_ne_bytes())
+            .unwrap();
+    }
+    match device.call_with_push_constants(&compute, (8, 1, 1), &args.1, &[0; 8]) {
+        Err(alkomp::CallError::PushConstantSize { expected, found }) => {
+            assert_eq!((expected, found), (4, 8))
+        }
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("8 bytes of push constants should not fit a 4 byte range"),
+    }
+
+    let data = device.get_blocking(&data_gpu).unwrap();
+    assert_eq!(&[6; 8], &data[..]);
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn call_with_retry_succeeds_first_time() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Data {
+        uint[] data;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        data[index] = index;
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let arr: Vec<u32> = vec![0; 16];
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let data_gpu = device.to_device(arr.as_slice());
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&data_gpu))
+        .build(Some(0));
+    let compute = device.compile("main", &shader, &args.0).unwrap();
+
+    device
+        .call_with_retry(&compute, (arr.len() as u32, 1, 1), &args.1, 3)
+        .unwrap();
+
+    let data = device.get_blocking(&data_gpu).unwrap();
+    let expected: Vec<u32> = (0..16).collect();
+    assert_eq!(&data[0..arr.len()], expected.as_slice());
+}
+
+fn out_of_memory() -> alkomp::CallError {
+    alkomp::CallError::OutOfMemory {
+        attempts: 1,
+        error: wgpu::Error::OutOfMemory {
+            source: Box::new(std::io::Error::other("simulated allocation failure")),
+        },
+    }
+}
+
+#[test]
+fn retry_out_of_memory_runs_the_attempt_again() {
+    let mut device = alkomp::Device::new(0).unwrap();
+
+    let mut attempts = 0;
+    let result = device.retry_out_of_memory(2, |_| {
+        attempts += 1;
+        if attempts == 1 {
+            return Err(out_of_memory());
+        }
+        Ok(attempts)
+    });
+    assert_eq!(result.unwrap(), 2);
+
+    match device.retry_out_of_memory(1, |_| -> Result<(), _> { Err(out_of_memory()) }) {
+        Err(alkomp::CallError::OutOfMemory { attempts, .. }) => assert_eq!(attempts, 2),
+        _ => panic!("expected to give up after one retry"),
+    }
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn call_timed_runs_the_dispatch() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Data {
+        uint[] data;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        data[index] = index;
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let arr: Vec<u32> = vec![0; 16];
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let data_gpu = device.to_device(arr.as_slice());
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&data_gpu))
+        .build(Some(0));
+    let compute = device.compile("main", &shader, &args.0).unwrap();
+
+    let elapsed = device
+        .call_timed(&compute, (arr.len() as u32, 1, 1), &args.1)
+        .unwrap();
+    if device
+        .device
+        .features()
+        .contains(wgpu::Features::TIMESTAMP_QUERY)
+    {
+        assert!(elapsed.unwrap() > 0);
+    } else {
+        // The adapter can't time dispatches, but the kernel runs either way
+        assert_eq!(elapsed, None);
+    }
+
+    let data = device.get_blocking(&data_gpu).unwrap();
+    let expected: Vec<u32> = (0..16).collect();
+    assert_eq!(&data[0..arr.len()], expected.as_slice());
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn call_indirect_reads_workgroup_counts() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Data {
+        uint[] data;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        data[index] = index + 1;
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let arr: Vec<u32> = vec![0; 16];
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let data_gpu = device.to_device(arr.as_slice());
+    // Only the first 10 elements are covered by the dispatch
+    let indirect = device.to_device_indirect(&[0, 10, 1, 1]);
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&data_gpu))
+        .build(Some(0));
+    let compute = device.compile("main", &shader, &args.0).unwrap();
+
+    // Unaligned, past the end and non-indirect buffers are rejected before recording
+    for offset in [2, 8].iter() {
+        match device.call_indirect(&compute, &indirect, *offset, &args.1) {
+            Err(alkomp::CallError::IndirectOffset { buffer_size, .. }) => {
+                assert_eq!(buffer_size, 16)
+            }
+            _ => panic!("expected an IndirectOffset error for offset {}", offset),
+        }
+    }
+    let plain = device.to_device(&[1u32, 1, 1]);
+    match device.call_indirect(&compute, &plain, 0, &args.1) {
+        Err(alkomp::CallError::NotIndirect) => {}
+        _ => panic!("expected a NotIndirect error"),
+    }
+
+    device
+        .call_indirect(&compute, &indirect, 4, &args.1)
+        .unwrap();
+
+    let data = device.get_blocking(&data_gpu).unwrap();
+    let expected: Vec<u32> = (1..11).chain(std::iter::repeat(0).take(6)).collect();
+    assert_eq!(&data[0..arr.len()], expected.as_slice());
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn batched_passes_see_earlier_writes() {
+    let fill = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Data {
+        uint[] data;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        data[index] = index;
+    }";
+    let twice = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Data {
+        uint[] data;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        data[index] *= 2;
+    }";
+
+    let fill = alkomp::glslhelper::GLSLCompile::new(&fill)
+        .compile("main")
+        .unwrap();
+    let twice = alkomp::glslhelper::GLSLCompile::new(&twice)
+        .compile("main")
+        .unwrap();
+
+    let arr: Vec<u32> = vec![0; 16];
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let data_gpu = device.to_device(arr.as_slice());
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&data_gpu))
+        .build(Some(0));
+    let fill = device.compile("main", &fill, &args.0).unwrap();
+    let twice = device.compile("main", &twice, &args.0).unwrap();
+
+    let mut batch = device.begin_batch();
+    batch.add(&fill, (16, 1, 1), &args.1).unwrap();
+    batch.add(&twice, (16, 1, 1), &args.1).unwrap();
+    batch.add(&twice, (16, 1, 1), &args.1).unwrap();
+    batch.submit();
+
+    let data = device.get_blocking(&data_gpu).unwrap();
+    let expected: Vec<u32> = (0..16).map(|i| i * 4).collect();
+    assert_eq!(&data[0..arr.len()], expected.as_slice());
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn counter_is_reset_between_dispatches() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Data {
+        uint[] data;
+    };
+
+    layout(set = 0, binding = 1) buffer Counter {
+        uint count;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        if (data[index] % 2 == 0) {
+            atomicAdd(count, 1);
+        }
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let arr: Vec<u32> = (0..16).collect();
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let data_gpu = device.to_device(arr.as_slice());
+    let counter = alkomp::ParamsBuilder::counter(&mut device, 5);
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&data_gpu))
+        .param(Some(&counter))
+        .build(Some(0));
+    let compute = device.compile("main", &shader, &args.0).unwrap();
+
+    device
+        .call_cached(&compute, (16, 1, 1), &args.1, &[])
+        .unwrap();
+    let count = device.get_blocking(&counter).unwrap();
+    assert_eq!(&[13], &count[..]);
+
+    device.reset_counter(&counter);
+    device
+        .call_cached(&compute, (16, 1, 1), &args.1, &[])
+        .unwrap();
+    let count = device.get_blocking(&counter).unwrap();
+    assert_eq!(&[8], &count[..]);
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Particle {
+    pos: [f32; 3],
+    vel: [f32; 3],
+    mass: f32,
+}
+
+unsafe impl bytemuck::Zeroable for Particle {}
+unsafe impl bytemuck::Pod for Particle {}
+
+#[test]
+fn get_fields_splits_structs() {
+    let particles: Vec<Particle> = (0..10)
+        .map(|i| Particle {
+            pos: [i as f32, 0.0, 1.0],
+            vel: [0.0, i as f32 * 2.0, 0.0],
+            mass: i as f32 + 0.5,
+        })
+        .collect();
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let particles_gpu = device.to_device(particles.as_slice());
+
+    let (pos, vel, mass) = futures::executor::block_on(alkomp::get_fields!(
+        device,
+        &particles_gpu,
+        Particle { pos, vel, mass }
+    ))
+    .unwrap();
+
+    assert_eq!(pos.len(), 10);
+    for (i, p) in particles.iter().enumerate() {
+        assert_eq!(pos[i], p.pos);
+        assert_eq!(vel[i], p.vel);
+        assert_eq!(mass[i], p.mass);
+    }
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn validate_args_before_call() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Input {
+        uint[] a;
+    };
+
+    layout(set = 0, binding = 1) buffer Output {
+        uint[] b;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        b[index] = a[index];
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let a = device.to_device(&[1u32, 2, 3, 4]);
+    let b = device.to_device(&[0u32; 4]);
+    let c = device.to_device(&[0u32; 4]);
+
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&a))
+        .param(Some(&b))
+        .build(Some(0));
+    let compute = device.compile("main", &shader, &args.0).unwrap();
+    assert_eq!(compute.validate_args(&args.1), Ok(()));
+
+    let missing = alkomp::ParamsBuilder::new().param(Some(&a)).build(Some(0));
+    assert_eq!(
+        compute.validate_args(&missing.1),
+        Err(alkomp::ValidationError::MissingBinding { set: 0, binding: 1 })
+    );
+
+    let extra = alkomp::ParamsBuilder::new()
+        .param(Some(&a))
+        .param(Some(&b))
+        .param(Some(&c))
+        .build(Some(0));
+    assert_eq!(
+        compute.validate_args(&extra.1),
+        Err(alkomp::ValidationError::ExtraBinding { binding: 2 })
+    );
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn validate_args_checks_readonly_buffers() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) readonly buffer Input {
+        uint[] a;
+    };
+
+    layout(set = 0, binding = 1) buffer Output {
+        uint[] b;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        b[index] = a[index];
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+    assert_eq!(alkomp::spirv::readonly_bindings(&shader), vec![(0, 0)]);
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let a = device.to_device(&[1u32, 2, 3, 4]);
+    let b = device.to_device(&[0u32; 4]);
+
+    let args = alkomp::ParamsBuilder::new()
+        .param_readonly(Some(&a))
+        .param(Some(&b))
+        .build(Some(0));
+    let compute = device.compile("main", &shader, &args.0).unwrap();
+    assert_eq!(compute.validate_args(&args.1), Ok(()));
+
+    // A writable layout for a readonly buffer compiles, but doesn't match the shader
+    let writable = alkomp::ParamsBuilder::new()
+        .param(Some(&a))
+        .param(Some(&b))
+        .build(Some(0));
+    let compute = device.compile("main", &shader, &writable.0).unwrap();
+    assert_eq!(
+        compute.validate_args(&writable.1),
+        Err(alkomp::ValidationError::ReadOnlyMismatch {
+            set: 0,
+            binding: 0,
+            layout_read_only: false,
+        })
+    );
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn heat_diffusion_on_3d_grid() {
+    let code = "
+    #version 450
+    layout(local_size_x = 4, local_size_y = 4, local_size_z = 2) in;
+
+    layout(set = 0, binding = 0) buffer Dims {
+        uvec3 dims;
+    };
+
+    layout(set = 0, binding = 1) buffer Input {
+        float[] t_in;
+    };
+
+    layout(set = 0, binding = 2) buffer Output {
+        float[] t_out;
+    };
+
+    float at(ivec3 p) {
+        p = clamp(p, ivec3(0), ivec3(dims) - 1);
+        return t_in[(p.z * dims.y + p.y) * dims.x + p.x];
+    }
+
+    void main() {
+        uvec3 c = gl_GlobalInvocationID;
+        if (any(greaterThanEqual(c, dims))) {
+            return;
+        }
+        ivec3 p = ivec3(c);
+        float sum = at(p + ivec3(1, 0, 0)) + at(p - ivec3(1, 0, 0))
+            + at(p + ivec3(0, 1, 0)) + at(p - ivec3(0, 1, 0))
+            + at(p + ivec3(0, 0, 1)) + at(p - ivec3(0, 0, 1));
+        t_out[(c.z * dims.y + c.y) * dims.x + c.x] = at(p) + 0.1 * (sum - 6.0 * at(p));
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    // Not a multiple of the workgroup size along any axis
+    let (nx, ny, nz) = (9usize, 6usize, 5usize);
+    let idx = |x: usize, y: usize, z: usize| (z * ny + y) * nx + x;
+    let mut t = vec![0f32; nx * ny * nz];
+    t[idx(4, 2, 1)] = 100.0;
+    t[idx(0, 5, 4)] = 50.0;
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let dims_gpu = device.to_device(&[nx as u32, ny as u32, nz as u32, 0]);
+    let t_in = device.to_device(t.as_slice());
+    let t_out = device.to_device(vec![0f32; t.len()].as_slice());
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&dims_gpu))
+        .param(Some(&t_in))
+        .param(Some(&t_out))
+        .build(Some(0));
+    let compute = device.compile("main", &shader, &args.0).unwrap();
+
+    device
+        .dispatch_grid(
+            &compute,
+            (nx as u32, ny as u32, nz as u32),
+            (4, 4, 2),
+            &args.1,
+        )
+        .unwrap();
+
+    let result = device.get_blocking(&t_out).unwrap();
+
+    let at = |x: isize, y: isize, z: isize| {
+        let x = x.max(0).min(nx as isize - 1) as usize;
+        let y = y.max(0).min(ny as isize - 1) as usize;
+        let z = z.max(0).min(nz as isize - 1) as usize;
+        t[idx(x, y, z)]
+    };
+    for z in 0..nz as isize {
+        for y in 0..ny as isize {
+            for x in 0..nx as isize {
+                let sum = at(x + 1, y, z)
+                    + at(x - 1, y, z)
+                    + at(x, y + 1, z)
+                    + at(x, y - 1, z)
+                    + at(x, y, z + 1)
+                    + at(x, y, z - 1);
+                let expected = at(x, y, z) + 0.1 * (sum - 6.0 * at(x, y, z));
+                let got = result[idx(x as usize, y as usize, z as usize)];
+                assert!((got - expected).abs() < 1e-4);
+            }
+        }
+    }
+
+    match device.dispatch_grid(&compute, (1 << 30, 1, 1), (4, 4, 2), &args.1) {
+        Err(alkomp::CallError::TooManyWorkgroups { axis: 0, .. }) => {}
+        _ => panic!("expected too many workgroups along x"),
+    }
+    match device.dispatch_grid(&compute, (nx as u32, 1, 1), (1, 1, 1), &args.1) {
+        Err(alkomp::CallError::LocalSizeMismatch { expected, found }) => {
+            assert_eq!((expected, found), ((4, 4, 2), (1, 1, 1)))
+        }
+        _ => panic!("expected a workgroup size mismatch"),
+    }
+    match device.dispatch_grid(&compute, (nx as u32, 1, 1), (4, 0, 2), &args.1) {
+        Err(alkomp::CallError::ZeroLocalSize) => {}
+        _ => panic!("expected a zero workgroup size to be rejected"),
+    }
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn export_trace_writes_chrome_events() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Data {
+        uint[] data;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        data[index] += 1;
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let data_gpu = device.to_device(&[0u32; 16]);
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&data_gpu))
+        .build(Some(0));
+
+    device.set_tracing(true);
+    for _ in 0..3 {
+        let compute = device.compile("main", &shader, &args.0).unwrap();
+        device.call(&compute, (16, 1, 1), &args.1).unwrap();
+    }
+
+    let nanos = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .subsec_nanos();
+    let path = std::env::temp_dir().join(format!(
+        "alkomp_trace_{}_{}.json",
+        std::process::id(),
+        nanos
+    ));
+    device.export_trace(&path).unwrap();
+    let trace = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert!(trace.starts_with("{\"traceEvents\":["));
+    assert_eq!(trace.matches("\"name\":\"main\"").count(), 3);
+    assert_eq!(trace.matches("\"ph\":\"X\"").count(), 3);
+}
+
+#[cfg(feature = "glsl")]
+#[derive(Clone, Copy)]
+#[repr(C)]
+struct Step {
+    scale: f32,
+    slot: u32,
+    // Pads each step to the 256 byte dynamic offset alignment
+    _pad: [[u32; 31]; 2],
+}
+
+#[cfg(feature = "glsl")]
+unsafe impl bytemuck::Zeroable for Step {}
+#[cfg(feature = "glsl")]
+unsafe impl bytemuck::Pod for Step {}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn uniform_dynamic_offset_sweep() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) uniform Params {
+        float scale;
+        uint slot;
+    };
+
+    layout(set = 0, binding = 1) buffer Output {
+        float[] data_out;
+    };
+
+    void main() {
+        data_out[slot] = scale * 2.0;
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let steps: Vec<Step> = (0..8)
+        .map(|i| Step {
+            scale: i as f32 * 0.5,
+            slot: i,
+            _pad: [[0; 31]; 2],
+        })
+        .collect();
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let steps_gpu = device.to_device_uniform(steps.as_slice());
+    let output = device.to_device(&[0f32; 8]);
+    let args = alkomp::ParamsBuilder::new()
+        .uniform_dynamic(Some(&steps_gpu))
+        .unwrap()
+        .param(Some(&output))
+        .build(Some(0));
+    let compute = device.compile("main", &shader, &args.0).unwrap();
+
+    for i in 0..steps.len() {
+        let offset = (i * std::mem::size_of::<Step>()) as u32;
+        if i % 2 == 0 {
+            device
+                .dispatch(&compute)
+                .args(&args.1)
+                .dynamic_offsets(&[offset])
+                .run()
+                .unwrap();
+        } else {
+            device
+                .call_with_dynamic_offsets(&compute, (1, 1, 1), &args.1, &[offset])
+                .unwrap();
+        }
+    }
+    // Without offsets the dynamic binding shows the first step
+    device.call(&compute, (1, 1, 1), &args.1).unwrap();
+
+    let result = device.get_blocking(&output).unwrap();
+    let expected: Vec<f32> = steps.iter().map(|s| s.scale * 2.0).collect();
+    assert_eq!(&result[0..8], expected.as_slice());
+
+    let misaligned = device
+        .dispatch(&compute)
+        .args(&args.1)
+        .dynamic_offsets(&[4])
+        .run();
+    assert!(misaligned.is_err());
+
+    match device.call_with_dynamic_offsets(&compute, (1, 1, 1), &args.1, &[0, 256]) {
+        Err(alkomp::CallError::DynamicOffsetCount { expected, found }) => {
+            assert_eq!((expected, found), (1, 2))
+        }
+        _ => panic!("expected one offset per dynamic binding"),
+    }
+    let past_end = (steps.len() * std::mem::size_of::<Step>()) as u32;
+    match device.call_with_dynamic_offsets(&compute, (1, 1, 1), &args.1, &[past_end]) {
+        Err(alkomp::CallError::DynamicOffsetOutOfBounds { binding, .. }) => assert_eq!(binding, 0),
+        _ => panic!("expected the offset to run past the end of the buffer"),
+    }
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn reusable_bindings_across_pipelines() {
+    let double = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Input {
+        uint[] a;
+    };
+
+    layout(set = 0, binding = 1) buffer Output {
+        uint[] b;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        b[index] = a[index] * 2;
+    }";
+    let add = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Input {
+        uint[] a;
+    };
+
+    layout(set = 0, binding = 1) buffer Output {
+        uint[] b;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        b[index] += a[index];
+    }";
+
+    let double = alkomp::glslhelper::GLSLCompile::new(&double)
+        .compile("main")
+        .unwrap();
+    let add = alkomp::glslhelper::GLSLCompile::new(&add)
+        .compile("main")
+        .unwrap();
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let a = device.to_device(&[1u32, 2, 3, 4]);
+    let b = device.to_device(&[0u32; 4]);
+
+    let layout = alkomp::ParamsBuilder::new()
+        .param::<u32>(None)
+        .param::<u32>(None)
+        .build(Some(0))
+        .0;
+    let bindings = device
+        .make_bindings(&layout, &[&a.storage_buffer, &b.storage_buffer])
+        .unwrap();
+
+    let first = device.compile("main", &double, &layout).unwrap();
+    let second = device.compile("main", &add, &layout).unwrap();
+    device.call(&first, (4, 1, 1), &bindings).unwrap();
+    device.call(&second, (4, 1, 1), &bindings).unwrap();
+
+    let result = device.get_blocking(&b).unwrap();
+    assert_eq!(&result[0..4], &[3, 6, 9, 12]);
+
+    assert!(device.make_bindings(&layout, &[&a.storage_buffer]).is_err());
+}
+
+#[test]
+fn missing_adapter_is_an_error() {
+    match alkomp::Device::new(1000) {
+        Err(alkomp::DeviceError::AdapterNotFound(index)) => assert_eq!(index, 1000),
+        Err(alkomp::DeviceError::NoBackends) => {}
+        Err(e) => panic!("unexpected error: {}", e),
+        Ok(_) => panic!("there should be no adapter at index 1000"),
+    }
+}
+
+#[test]
+fn query_indices_select_the_adapter() {
+    let devices = alkomp::query();
+    for (position, info) in devices.iter().enumerate() {
+        assert_eq!(info.index(), Some(position));
+    }
+    let device = alkomp::Device::new(0).unwrap();
+    let info = device.info.as_ref().unwrap();
+    assert_eq!(info.index(), Some(0));
+    assert_eq!(info.name(), devices[0].name());
+}
+
+#[test]
+fn query_by_backend_keeps_the_query_indices() {
+    let devices = alkomp::query();
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends: wgpu::Backends::PRIMARY,
+        ..Default::default()
+    });
+    let listed = alkomp::query_with_instance(&instance);
+    assert_eq!(listed.len(), devices.len());
+    for (info, other) in listed.iter().zip(devices.iter()) {
+        assert_eq!(info.index(), other.index());
+        assert_eq!(info.name(), other.name());
+    }
+
+    let backend = wgpu::Backends::from(devices[0].info.backend);
+    let filtered = alkomp::query_by_backend(backend);
+    assert!(!filtered.is_empty());
+    for info in &filtered {
+        assert_eq!(info.info.backend, devices[0].info.backend);
+        let index = info.index().unwrap();
+        assert_eq!(info.name(), devices[index].name());
+
+        // The index of a filtered adapter still selects it
+        let device = alkomp::Device::from_instance(&instance, index).unwrap();
+        assert_eq!(device.info.as_ref().unwrap().name(), info.name());
+    }
+}
+
+#[test]
+fn fallback_device_runs_transfers() {
+    let mut device = alkomp::Device::new_fallback().unwrap();
+    let arr: Vec<u32> = (0..8).collect();
+    let data_gpu = device.to_device(arr.as_slice());
+    let data = device.get_blocking(&data_gpu).unwrap();
+    assert_eq!(&arr[..], &data[..]);
+}
+
+#[test]
+fn mappable_buffers_are_read_directly() {
+    let mut device = alkomp::Device::new(0).unwrap();
+    let arr: Vec<u32> = (0..16).collect();
+    let data_gpu = device.to_device_mappable(arr.as_slice());
+    assert!(data_gpu.is_mappable());
+
+    let data = device.get_blocking(&data_gpu).unwrap();
+    assert_eq!(&arr[..], &data[..]);
+
+    device.update(&data_gpu, &[3u32; 16]);
+    let data = device.get_blocking(&data_gpu).unwrap();
+    assert_eq!(&[3u32; 16], &data[..]);
+}
+
+#[test]
+fn device_with_default_limits() {
+    let mut device =
+        alkomp::Device::new_with(0, wgpu::Features::empty(), wgpu::Limits::default()).unwrap();
+    let arr: Vec<u32> = (0..16).collect();
+    let data_gpu = device.to_device(arr.as_slice());
+    let data = device.get_blocking(&data_gpu).unwrap();
+    assert_eq!(&data[0..arr.len()], arr.as_slice());
+}
+
+#[test]
+fn get_range_reads_a_slice() {
+    let mut device = alkomp::Device::new(0).unwrap();
+    let arr: Vec<u16> = (0..101).collect();
+    let data_gpu = device.to_device(arr.as_slice());
+
+    let data = futures::executor::block_on(device.get_range(&data_gpu, 3..10)).unwrap();
+    assert_eq!(&*data, &arr[3..10]);
+    let data = futures::executor::block_on(device.get_range(&data_gpu, 95..101)).unwrap();
+    assert_eq!(&*data, &arr[95..101]);
+    assert!(
+        futures::executor::block_on(device.get_range(&data_gpu, 5..5))
+            .unwrap()
+            .is_empty()
+    );
+    assert_eq!(
+        futures::executor::block_on(device.get_range(&data_gpu, 100..102)).unwrap_err(),
+        alkomp::GetError::OutOfBounds {
+            start: 100,
+            end: 102,
+            len: 101
+        }
+    );
+}
+
+#[test]
+fn get_reads_back_in_chunks() {
+    let mut device = alkomp::Device::new(0).unwrap();
+    device.set_readback_chunk_size(24);
+    assert_eq!(device.readback_chunk_size(), 24);
+    let arr: Vec<u32> = (0..101).collect();
+    let data_gpu = device.to_device(arr.as_slice());
+
+    assert_eq!(&device.get_blocking(&data_gpu).unwrap()[..], &arr[..]);
+
+    let token = alkomp::CancelToken::new();
+    let data = device.get_cancellable(&data_gpu, &token).unwrap();
+    assert_eq!(&data[..], &arr[..]);
+
+    token.cancel();
+    assert_eq!(
+        device.get_cancellable(&data_gpu, &token).err(),
+        Some(alkomp::GetError::Cancelled)
+    );
+}
+
+#[test]
+fn readback_after_a_cancelled_map() {
+    let mut device = alkomp::Device::new(0).unwrap();
+    let arr: Vec<u32> = (0..1 << 20).collect();
+    let data_gpu = device.to_device(arr.as_slice());
+
+    let token = alkomp::CancelToken::new();
+    let canceller = token.clone();
+    let thread = std::thread::spawn(move || canceller.cancel());
+    // Whether it finishes or is cancelled mid-map, the staging buffer ends up unmapped
+    let _ = device.get_cancellable(&data_gpu, &token);
+    thread.join().unwrap();
+    assert_eq!(&device.get_blocking(&data_gpu).unwrap()[..], &arr[..]);
+
+    // The staging buffer goes back to the pool and serves the next upload of the same size
+    drop(data_gpu);
+    let again = device.to_device(arr.as_slice());
+    assert_eq!(&device.get_blocking(&again).unwrap()[..], &arr[..]);
+}
+
+#[test]
+fn copy_snapshots_a_buffer() {
+    let mut device = alkomp::Device::new(0).unwrap();
+    let arr: Vec<u32> = (0..16).collect();
+    let src = device.to_device(arr.as_slice());
+    let dst = device.to_device(&[0u32; 8]);
+
+    device.copy(&src, &dst);
+    device.update(&src, &[7u32; 16]);
+
+    let data = device.get_blocking(&dst).unwrap();
+    assert_eq!(&data[0..8], &arr[0..8]);
+}
+
+#[test]
+fn cloned_data_is_independent() {
+    let mut device = alkomp::Device::new(0).unwrap();
+    let arr: Vec<u16> = (0..15).collect();
+    let src = device.to_device(arr.as_slice());
+
+    let copy = device.clone_gpu_data(&src);
+    device.update(&src, &[9u16; 15]);
+
+    assert_eq!(copy.len(), arr.len());
+    assert_eq!(&device.get_blocking(&copy).unwrap()[..], &arr[..]);
+    assert_eq!(&device.get_blocking(&src).unwrap()[..], &[9u16; 15][..]);
+}
+
+#[test]
+fn clear_zeroes_a_buffer() {
+    let mut device = alkomp::Device::new(0).unwrap();
+    let data_gpu = device.to_device(&[7u16; 5]);
+    device.clear(&data_gpu);
+    assert_eq!(&device.get_blocking(&data_gpu).unwrap()[..], &[0u16; 5][..]);
+}
+
+#[test]
+fn alloc_without_upload() {
+    let mut device = alkomp::Device::new(0).unwrap();
+    let zeros = device.alloc_zeroed::<f32>(100);
+    assert_eq!(zeros.len(), 100);
+    assert_eq!(&device.get_blocking(&zeros).unwrap()[..], &[0f32; 100][..]);
+
+    let arr: Vec<u32> = (0..8).collect();
+    let src = device.to_device(arr.as_slice());
+    let dst = device.alloc::<u32>(8);
+    device.copy(&src, &dst);
+    assert_eq!(&device.get_blocking(&dst).unwrap()[..], &arr[..]);
+}
+
+#[test]
+fn get_into_reuses_the_output() {
+    let mut device = alkomp::Device::new(0).unwrap();
+    let arr: Vec<f32> = (0..33).map(|i| i as f32 * 0.5).collect();
+    let data_gpu = device.to_device(arr.as_slice());
+
+    let mut out = vec![0f32; arr.len()];
+    futures::executor::block_on(device.get_into(&data_gpu, &mut out)).unwrap();
+    assert_eq!(out, arr);
+
+    let mut short = vec![0f32; 4];
+    assert_eq!(
+        futures::executor::block_on(device.get_into(&data_gpu, &mut short)),
+        Err(alkomp::GetError::SizeMismatch {
+            expected: 33,
+            found: 4
+        })
+    );
+}
+
+#[test]
+fn poll_completes_pending_work() {
+    let mut device = alkomp::Device::new(0).unwrap();
+    let data_gpu = device.to_device(&[1u32, 2, 3, 4]);
+    device.update(&data_gpu, &[5u32, 6, 7, 8]);
+
+    device.poll(false);
+    device.poll(true);
+
+    let data = device.get_blocking(&data_gpu).unwrap();
+    assert_eq!(&data[..], &[5, 6, 7, 8]);
+}
+
+#[test]
+fn native_devices_support_mappable_primary() {
+    let device = alkomp::Device::new(0).unwrap();
+    assert!(device.supports_mappable_primary());
+}
+
+#[test]
+fn buffers_are_pooled() {
+    let mut device = alkomp::Device::new(0).unwrap();
+    let first = device.to_device(&[1u32; 64]);
+    drop(first);
+    assert_eq!(device.pooled_bytes(), 256);
+
+    for i in 0..16u32 {
+        let data_gpu = device.to_device(&[i; 64]);
+        assert_eq!(device.pooled_bytes(), 0);
+        assert_eq!(&device.get_blocking(&data_gpu).unwrap()[..], &[i; 64][..]);
+    }
+    assert_eq!(device.pooled_bytes(), 256);
+
+    // Only the staging buffer is reused, so the storage buffer may have another usage
+    let uniform = device.to_device_uniform(&[3u32; 64]);
+    assert_eq!(device.pooled_bytes(), 0);
+    drop(uniform);
+
+    device.clear_pool();
+    assert_eq!(device.pooled_bytes(), 0);
+}
+
+#[test]
+fn pool_stays_within_its_budget() {
+    let mut device = alkomp::Device::new(0).unwrap();
+    let allocated = device.total_allocated_bytes();
+    device.set_pool_budget(512);
+
+    // Two 256 byte staging buffers fit, the third is freed
+    let buffers: Vec<_> = (0..3).map(|_| device.to_device(&[1u32; 64])).collect();
+    drop(buffers);
+    assert_eq!(device.pooled_bytes(), 512);
+    assert_eq!(device.total_allocated_bytes(), allocated + 512);
+
+    device.set_pool_budget(256);
+    assert_eq!(device.pooled_bytes(), 0);
+    assert_eq!(device.total_allocated_bytes(), allocated);
+}
+
+#[test]
+fn readback_async_resolves_after_poll() {
+    let mut device = alkomp::Device::new(0).unwrap();
+    let arr: Vec<u32> = (0..32).collect();
+    let data_gpu = device.to_device(arr.as_slice());
+
+    let pending = device.readback_async(&data_gpu);
+    device.poll(true);
+    let data = futures::executor::block_on(pending).unwrap();
+    assert_eq!(&data[..], &arr[..]);
+}
+
+#[test]
+fn dropped_readback_async_cancels_the_map() {
+    let mut device = alkomp::Device::new(0).unwrap();
+    let arr: Vec<u32> = (0..32).collect();
+    let data_gpu = device.to_device(arr.as_slice());
+
+    drop(device.readback_async(&data_gpu));
+    device.poll(true);
+    assert_eq!(&device.get_blocking(&data_gpu).unwrap()[..], &arr[..]);
+}
+
+#[test]
+fn poll_thread_resolves_readbacks() {
+    let mut device = alkomp::Device::new(0).unwrap();
+    device.spawn_poll_thread();
+    let arr: Vec<u32> = (0..32).collect();
+    let data_gpu = device.to_device(arr.as_slice());
+
+    let data = futures::executor::block_on(device.readback_async(&data_gpu)).unwrap();
+    assert_eq!(&data[..], &arr[..]);
+}
+
+#[test]
+fn labeled_buffers_are_not_pooled() {
+    let mut device = alkomp::Device::new(0).unwrap().with_label("test");
+    let weights = device.to_device_labeled(&[1.5f32; 16], "weights");
+    assert_eq!(
+        &device.get_blocking(&weights).unwrap()[..],
+        &[1.5f32; 16][..]
+    );
+
+    drop(weights);
+    assert_eq!(device.pooled_bytes(), 0);
+}
+
+#[test]
+fn gpu_data_counts_elements() {
+    let mut device = alkomp::Device::new(0).unwrap();
+    let halves = device.to_device(&[1u16, 2, 3]);
+    assert_eq!(halves.len(), 3);
+    assert!(!halves.is_empty());
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn layout_validation_finds_mismatched_bindings() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Input {
+        uint[] input_data;
+    };
+
+    layout(set = 0, binding = 1) buffer Output {
+        uint[] output_data;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        output_data[index] = input_data[index];
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+    assert_eq!(alkomp::spirv::bindings(&shader), vec![(0, 0), (0, 1)]);
+
+    let layout = |count: usize| {
+        (0..count)
+            .fold(alkomp::ParamsBuilder::new(), |params, _| {
+                params.param::<u32>(None)
+            })
+            .build(Some(0))
+            .0
+    };
+    assert_eq!(layout(2).validate(&shader), Ok(()));
+    assert_eq!(
+        layout(1).validate(&shader),
+        Err(alkomp::CompileError::BindingMismatch { set: 0, binding: 1 })
+    );
+    assert_eq!(
+        layout(3).validate(&shader),
+        Err(alkomp::CompileError::BindingMismatch { set: 0, binding: 2 })
+    );
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn one_buffer_at_two_bindings() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Input {
+        uint[] input_data;
+    };
+
+    layout(set = 0, binding = 1) buffer Output {
+        uint[] output_data;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        output_data[index] = input_data[index] + 1;
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let arr: Vec<u32> = (0..8).collect();
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let data_gpu = device.to_device(arr.as_slice());
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&data_gpu))
+        .param(Some(&data_gpu))
+        .build(Some(0));
+    let compute = device.compile("main", &shader, &args.0).unwrap();
+    device.call(&compute, (8, 1, 1), &args.1).unwrap();
+
+    let data = device.get_blocking(&data_gpu).unwrap();
+    let expected: Vec<u32> = (1..9).collect();
+    assert_eq!(&expected[..], &data[..]);
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn param_at_sparse_bindings() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Input {
+        uint[] input_data;
+    };
+
+    layout(set = 0, binding = 3) buffer Output {
+        uint[] output_data;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        output_data[index] = input_data[index] * 3;
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let arr: Vec<u32> = (0..8).collect();
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let input_gpu = device.to_device(arr.as_slice());
+    let output_gpu = device.to_device(vec![0u32; arr.len()].as_slice());
+    let args = alkomp::ParamsBuilder::new()
+        .param_at(3, Some(&output_gpu))
+        .param_at(0, Some(&input_gpu))
+        .build(Some(0));
+    let compute = device.compile("main", &shader, &args.0).unwrap();
+    device.call(&compute, (8, 1, 1), &args.1).unwrap();
+
+    let data = device.get_blocking(&output_gpu).unwrap();
+    let expected: Vec<u32> = arr.iter().map(|x| x * 3).collect();
+    assert_eq!(&expected[..], &data[..]);
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn pipelines_share_a_loaded_module() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Data {
+        uint[] data;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        data[index] = data[index] + 10;
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let first_gpu = device.to_device(&[1u32, 2, 3, 4]);
+    let second_gpu = device.to_device(&[5u32, 6]);
+    let first = alkomp::ParamsBuilder::new()
+        .param(Some(&first_gpu))
+        .build(Some(0));
+    let second = alkomp::ParamsBuilder::new()
+        .param(Some(&second_gpu))
+        .build(Some(0));
+
+    let module = device.load_module(&shader);
+    let compute = device.compile_entry(&module, "main", &first.0).unwrap();
+    let other = device.compile_entry(&module, "main", &second.0).unwrap();
+    device.call(&compute, (4, 1, 1), &first.1).unwrap();
+    device.call(&other, (2, 1, 1), &second.1).unwrap();
+
+    assert_eq!(
+        &device.get_blocking(&first_gpu).unwrap()[..],
+        &[11, 12, 13, 14]
+    );
+    assert_eq!(&device.get_blocking(&second_gpu).unwrap()[..], &[15, 16]);
+}
+
+/// One SPIR-V instruction, its word count and opcode followed by `operands`
+fn op(opcode: u32, operands: &[u32]) -> Vec<u32> {
+    let mut words = vec![(operands.len() as u32 + 1) << 16 | opcode];
+    words.extend_from_slice(operands);
+    words
+}
+
+/// A nul-terminated SPIR-V literal string
+fn literal(name: &str) -> Vec<u32> {
+    let mut bytes = name.as_bytes().to_vec();
+    bytes.resize(name.len() / 4 * 4 + 4, 0);
+    bytes
+        .chunks(4)
+        .map(|c| u32::from_le_bytes([c[0], c[1], c[2], c[3]]))
+        .collect()
+}
+
+#[test]
+fn compile_entry_picks_the_entry_point() {
+    // A module with two entry points over `layout(binding = 0) buffer { uint data[]; }`,
+    // which shaderc can't produce: `add` adds 10 to each element and `double` doubles it
+    let entry = |id: u32, name: &str| {
+        let mut operands = vec![5, id];
+        operands.extend(literal(name));
+        operands.push(6);
+        op(15, &operands)
+    };
+    let body = |id: u32, arithmetic: u32, constant: u32| {
+        let ids = id + 1;
+        [
+            op(54, &[1, id, 0, 2]),
+            op(248, &[ids]),
+            op(65, &[7, ids + 1, 6, 15]),
+            op(61, &[3, ids + 2, ids + 1]),
+            op(65, &[12, ids + 3, 11, 14, ids + 2]),
+            op(61, &[3, ids + 4, ids + 3]),
+            op(arithmetic, &[3, ids + 5, ids + 4, constant]),
+            op(62, &[ids + 3, ids + 5]),
+            op(253, &[]),
+            op(56, &[]),
+        ]
+        .concat()
+    };
+    let shader: Vec<u32> = vec![
+        vec![0x0723_0203, 0x0001_0000, 0, 32, 0],
+        op(17, &[1]),
+        op(14, &[0, 1]),
+        entry(18, "add"),
+        entry(25, "double"),
+        op(16, &[18, 17, 1, 1, 1]),
+        op(16, &[25, 17, 1, 1, 1]),
+        op(71, &[6, 11, 28]),
+        op(71, &[8, 6, 4]),
+        op(72, &[9, 0, 35, 0]),
+        op(71, &[9, 3]),
+        op(71, &[11, 34, 0]),
+        op(71, &[11, 33, 0]),
+        op(19, &[1]),
+        op(33, &[2, 1]),
+        op(21, &[3, 32, 0]),
+        op(23, &[4, 3, 3]),
+        op(32, &[5, 1, 4]),
+        op(59, &[5, 6, 1]),
+        op(32, &[7, 1, 3]),
+        op(29, &[8, 3]),
+        op(30, &[9, 8]),
+        op(32, &[10, 2, 9]),
+        op(59, &[10, 11, 2]),
+        op(32, &[12, 2, 3]),
+        op(21, &[13, 32, 1]),
+        op(43, &[13, 14, 0]),
+        op(43, &[3, 15, 0]),
+        op(43, &[3, 16, 10]),
+        op(43, &[3, 17, 2]),
+        body(18, 128, 16),
+        body(25, 132, 17),
+    ]
+    .concat();
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let data_gpu = device.to_device(&[1u32, 2, 3, 4]);
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&data_gpu))
+        .build(Some(0));
+
+    let module = device.load_module(&shader);
+    let add = device.compile_entry(&module, "add", &args.0).unwrap();
+    let double = device.compile_entry(&module, "double", &args.0).unwrap();
+    assert_eq!(add.local_size(), Some((1, 1, 1)));
+    device.call(&add, (4, 1, 1), &args.1).unwrap();
+    device.call(&double, (4, 1, 1), &args.1).unwrap();
+
+    assert_eq!(
+        &device.get_blocking(&data_gpu).unwrap()[..],
+        &[22, 24, 26, 28]
+    );
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn f64_buffers_square_exactly() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Data {
+        double[] data;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        data[index] = data[index] * data[index];
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+    assert!(alkomp::spirv::uses_float64(&shader));
+
+    // Without the feature the shader is rejected before wgpu sees it
+    let plain =
+        alkomp::Device::new_with(0, wgpu::Features::empty(), wgpu::Limits::default()).unwrap();
+    let layout = alkomp::ParamsBuilder::new().build(Some(0)).0;
+    match plain.compile("main", &shader, &layout) {
+        Err(alkomp::CompileError::MissingFeature(feature)) => {
+            assert_eq!(feature, wgpu::Features::SHADER_F64)
+        }
+        _ => panic!("expected doubles to need SHADER_F64"),
+    }
+    drop(plain);
+
+    let mut device =
+        match alkomp::Device::new_with(0, wgpu::Features::SHADER_F64, wgpu::Limits::default()) {
+            Ok(device) => device,
+            Err(alkomp::DeviceError::MissingFeatures(_)) => return,
+            Err(e) => panic!("{}", e),
+        };
+
+    let arr: Vec<f64> = vec![1.0 + 1e-12, 3.0, 0.1, -2.5e100];
+    let data_gpu = device.to_device(arr.as_slice());
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&data_gpu))
+        .build(Some(0));
+    let compute = device.compile("main", &shader, &args.0).unwrap();
+    device
+        .call(&compute, (arr.len() as u32, 1, 1), &args.1)
+        .unwrap();
+
+    let data = device.get_blocking(&data_gpu).unwrap();
+    let expected: Vec<f64> = arr.iter().map(|x| x * x).collect();
+    assert_eq!(&expected[..], &data[..]);
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn dispatch_1d_covers_a_partial_workgroup() {
+    let code = "
+    #version 450
+    layout(local_size_x = 64) in;
+
+    layout(set = 0, binding = 0) buffer Data {
+        uint[] data;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        if (index >= data.length()) {
+            return;
+        }
+        data[index] = data[index] + 1;
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let arr: Vec<u32> = (0..100).collect();
+    let mut device = alkomp::Device::new(0).unwrap();
+    let data_gpu = device.to_device(arr.as_slice());
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&data_gpu))
+        .build(Some(0));
+    let compute = device.compile("main", &shader, &args.0).unwrap();
+    match device.dispatch_1d(&compute, arr.len() as u32, 0, &args.1) {
+        Err(alkomp::CallError::ZeroLocalSize) => {}
+        _ => panic!("expected a zero local size to be rejected"),
+    }
+    device
+        .dispatch_1d(&compute, arr.len() as u32, 64, &args.1)
+        .unwrap();
+
+    let data = device.get_blocking(&data_gpu).unwrap();
+    let expected: Vec<u32> = (1..101).collect();
+    assert_eq!(&expected[..], &data[..]);
+}
+
+#[test]
+fn dispatch_threads_rounds_up_to_the_local_size() {
+    let code = "
+    @group(0) @binding(0) var<storage, read_write> data: array<u32>;
+
+    @compute @workgroup_size(4)
+    fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+        if (id.x >= arrayLength(&data)) {
+            return;
+        }
+        data[id.x] = data[id.x] + 1u;
+    }";
+
+    let arr: Vec<u32> = (0..10).collect();
+    let mut device = alkomp::Device::new(0).unwrap();
+    let data_gpu = device.to_device(arr.as_slice());
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&data_gpu))
+        .build(Some(0));
+    let compute = device.compile_wgsl("main", code, &args.0).unwrap();
+    device
+        .dispatch_threads(&compute, (arr.len() as u32, 1, 1), &args.1)
+        .unwrap();
+
+    let data = device.get_blocking(&data_gpu).unwrap();
+    let expected: Vec<u32> = (1..11).collect();
+    assert_eq!(&expected[..], &data[..]);
+
+    match device.compile_wgsl("main", "@compute fn main( {", &args.0) {
+        Err(alkomp::CompileError::InvalidWgsl(message)) => assert!(!message.is_empty()),
+        _ => panic!("expected the WGSL to be rejected"),
+    }
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn dispatch_2d_adds_matrices_with_tail_workgroups() {
+    let code = "
+    #version 450
+    layout(local_size_x = 16, local_size_y = 16) in;
+
+    layout(set = 0, binding = 0) buffer A {
+        float[] a;
+    };
+
+    layout(set = 0, binding = 1) buffer B {
+        float[] b;
+    };
+
+    const uint WIDTH = 100;
+    const uint HEIGHT = 100;
+
+    void main() {
+        uvec2 pos = gl_GlobalInvocationID.xy;
+        if (pos.x >= WIDTH || pos.y >= HEIGHT) {
+            return;
+        }
+        uint index = pos.y * WIDTH + pos.x;
+        a[index] = a[index] + b[index];
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let a: Vec<f32> = (0..100 * 100).map(|i| i as f32).collect();
+    let b: Vec<f32> = (0..100 * 100).map(|i| (i % 7) as f32).collect();
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let a_gpu = device.to_device(a.as_slice());
+    let b_gpu = device.to_device(b.as_slice());
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&a_gpu))
+        .param(Some(&b_gpu))
+        .build(Some(0));
+    let compute = device.compile("main", &shader, &args.0).unwrap();
+    device
+        .dispatch_2d(&compute, (100, 100), (16, 16), &args.1)
+        .unwrap();
+
+    let data = device.get_blocking(&a_gpu).unwrap();
+    let expected: Vec<f32> = a.iter().zip(b.iter()).map(|(x, y)| x + y).collect();
+    assert_eq!(&expected[..], &data[..]);
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn compute_with_two_sets() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Input {
+        uint[] a;
+    };
+
+    layout(set = 1, binding = 0) buffer Output {
+        uint[] b;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        b[index] = a[index] + 10;
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let a = device.to_device(&[1u32, 2, 3, 4]);
+    let b = device.to_device(&[0u32; 4]);
+
+    let inputs = alkomp::ParamsBuilder::new().param(Some(&a)).build(Some(0));
+    let outputs = alkomp::ParamsBuilder::new().param(Some(&b)).build(Some(1));
+    let layout = inputs.0.merge(outputs.0);
+
+    let mut sets = std::collections::HashMap::new();
+    sets.insert(0, inputs.1);
+    sets.insert(1, outputs.1);
+
+    let compute = device.compile("main", &shader, &layout).unwrap();
+    device.call_sets(&compute, (4, 1, 1), &sets).unwrap();
+
+    let result = device.get_blocking(&b).unwrap();
+    assert_eq!(&result[0..4], &[11, 12, 13, 14]);
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn compute_with_readonly_input() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) readonly buffer Input {
+        uint[] a;
+    };
+
+    layout(set = 0, binding = 1) buffer Output {
+        uint[] b;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        b[index] = a[index] * a[index];
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let a = device.to_device(&[1u32, 2, 3, 4]);
+    let b = device.to_device(&[0u32; 4]);
+
+    let args = alkomp::ParamsBuilder::new()
+        .param_readonly(Some(&a))
+        .param(Some(&b))
+        .build(Some(0));
+    let compute = device.compile("main", &shader, &args.0).unwrap();
+    device.call_checked(&compute, (4, 1, 1), &args.1).unwrap();
+
+    let result = device.get_blocking(&b).unwrap();
+    assert_eq!(&result[0..4], &[1, 4, 9, 16]);
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn compute_with_uniform_params() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Data {
+        float[] data;
+    };
+
+    layout(set = 0, binding = 1) uniform Params {
+        uint count;
+        float scale;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        if (index < count) {
+            data[index] = data[index] * scale;
+        }
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let data = device.to_device(&[1f32, 2.0, 3.0, 4.0]);
+    // count = 3, scale = 2.0
+    let params = device.to_device_uniform(&[3u32, 2f32.to_bits()]);
+
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&data))
+        .uniform(Some(&params))
+        .unwrap()
+        .build(Some(0));
+    let compute = device.compile("main", &shader, &args.0).unwrap();
+    device.call_checked(&compute, (4, 1, 1), &args.1).unwrap();
+
+    let result = device.get_blocking(&data).unwrap();
+    assert_eq!(&result[0..4], &[2.0, 4.0, 6.0, 4.0]);
+
+    // Storage-only buffers can't be bound as uniforms
+    match alkomp::ParamsBuilder::new()
+        .param(Some(&data))
+        .uniform(Some(&data))
+    {
+        Err(error) => assert_eq!(error, alkomp::ValidationError::NotUniform { binding: 1 }),
+        Ok(_) => panic!("expected a storage buffer to be rejected as a uniform"),
+    }
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn update_reuses_buffers() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Data {
+        uint[] data;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        data[index] = data[index] + 1;
+    }";
+
+    let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
+    let shader = spirv.compile("main").unwrap();
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let data_gpu = device.to_device(&[0u32; 8]);
+    let allocated = device.total_allocated_bytes();
+
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&data_gpu))
+        .build(Some(0));
+    for step in 0..10u32 {
+        device.update(&data_gpu, &[step * 10; 8]);
+        let compute = device.compile("main", &shader, &args.0).unwrap();
+        device.call(&compute, (8, 1, 1), &args.1).unwrap();
+
+        let result = device.get_blocking(&data_gpu).unwrap();
+        assert!(result[0..8].iter().all(|x| *x == step * 10 + 1));
+    }
+    assert_eq!(device.total_allocated_bytes(), allocated);
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn compile_glsl_at_runtime() {
+    let code = "
+    #version 450
+    layout(local_size_x = 1) in;
+
+    layout(set = 0, binding = 0) buffer Data {
+        uint[] data;
+    };
+
+    void main() {
+        uint index = gl_GlobalInvocationID.x;
+        data[index] = data[index] + 5;
+    }";
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let data_gpu = device.to_device(&[1u32, 2, 3]);
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&data_gpu))
+        .build(Some(0));
+
+    let compute = device
+        .compile_glsl(code, wgpu::ShaderStages::COMPUTE, "main", &args.0)
+        .unwrap();
+    device.call(&compute, (3, 1, 1), &args.1).unwrap();
+
+    let result = device.get_blocking(&data_gpu).unwrap();
+    assert_eq!(&result[0..3], &[6, 7, 8]);
+
+    let broken = "#version 450\nvoid main() { undeclared = 1; }";
+    match device.compile_glsl(broken, wgpu::ShaderStages::COMPUTE, "main", &args.0) {
+        Err(alkomp::CompileError::InvalidGlsl(message)) => assert!(message.contains("undeclared")),
+        _ => panic!("expected broken GLSL to be rejected"),
+    }
+    match device.compile_glsl(code, wgpu::ShaderStages::VERTEX, "main", &args.0) {
+        Err(alkomp::CompileError::UnsupportedStage(_)) => {}
+        _ => panic!("expected a vertex stage to be rejected"),
+    }
+}
+
+#[test]
+fn map_expr_transforms_in_place() {
+    let mut device = alkomp::Device::new(0).unwrap();
+    let arr: Vec<f32> = (0..100).map(|i| i as f32).collect();
+    let data_gpu = device.to_device(arr.as_slice());
+
+    device.map_expr(&data_gpu, "x * 2.0 + 1.0").unwrap();
+    device.map_expr(&data_gpu, "x * 2.0 + 1.0").unwrap();
+
+    let expected: Vec<f32> = arr.iter().map(|x| (x * 2.0 + 1.0) * 2.0 + 1.0).collect();
+    assert_eq!(&device.get_blocking(&data_gpu).unwrap()[..], &expected[..]);
+
+    let ints = device.to_device(&[1u32, 300, 7]);
+    device.map_expr(&ints, "min(x, 255u)").unwrap();
+    assert_eq!(&device.get_blocking(&ints).unwrap()[..], &[1, 255, 7]);
+}
+
+#[test]
+fn map_expr_rejects_invalid_expressions() {
+    let mut device = alkomp::Device::new(0).unwrap();
+    let data_gpu = device.to_device(&[1.0f32, 2.0, 3.0]);
+
+    // Doesn't parse
+    match device.map_expr(&data_gpu, "x +") {
+        Err(alkomp::CallError::Compile(alkomp::CompileError::InvalidWgsl(_))) => {}
+        _ => panic!("expected the expression to be rejected"),
+    }
+    // Parses but has the wrong type, caught by wgpu's validation
+    match device.map_expr(&data_gpu, "x > 1.0") {
+        Err(alkomp::CallError::Compile(alkomp::CompileError::InvalidWgsl(_))) => {}
+        _ => panic!("expected the expression to be rejected"),
+    }
+    match device.map_expr(&data_gpu, "x; data[0] = 0.0") {
+        Err(alkomp::CallError::Compile(alkomp::CompileError::InvalidWgsl(_))) => {}
+        _ => panic!("expected more than an expression to be rejected"),
+    }
+
+    device.map_expr(&data_gpu, "x + 1.0").unwrap();
+    assert_eq!(
+        &device.get_blocking(&data_gpu).unwrap()[..],
+        &[2.0, 3.0, 4.0]
+    );
+}
+
+#[test]
+fn map_expr_beyond_one_dispatch_row() {
+    let mut device = alkomp::Device::new(0).unwrap();
+    let max = device.device.limits().max_compute_workgroups_per_dimension as usize;
+    let n = max * 64 + 100;
+    let data_gpu = device.to_device(&vec![1u32; n]);
+
+    device.map_expr(&data_gpu, "x + 1u").unwrap();
+
+    let data = device.get_blocking(&data_gpu).unwrap();
+    assert!(data[..n].iter().all(|x| *x == 2));
+}
+
+#[test]
+fn sum_of_each_scalar_type() {
+    let mut device = alkomp::Device::new(0).unwrap();
+
+    let uints: Vec<u32> = (1..=100_000).collect();
+    let uints_gpu = device.to_device(uints.as_slice());
+    assert_eq!(device.sum(&uints_gpu).unwrap(), uints.iter().sum::<u32>());
+
+    let ints: Vec<i32> = (-500..300).collect();
+    let ints_gpu = device.to_device(ints.as_slice());
+    assert_eq!(device.sum(&ints_gpu).unwrap(), ints.iter().sum::<i32>());
+
+    let floats: Vec<f32> = (0..1000).map(|x| (x % 8) as f32 * 0.5).collect();
+    let floats_gpu = device.to_device(floats.as_slice());
+    assert_eq!(device.sum(&floats_gpu).unwrap(), floats.iter().sum::<f32>());
+}
+
+#[test]
+fn compute_with_wgsl() {
+    let code = "
+    @group(0) @binding(0) var<storage, read_write> data: array<u32>;
+
+    @compute @workgroup_size(4)
+    fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+        data[id.x] = data[id.x] * 3u;
+    }";
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let data_gpu = device.to_device(&[1u32, 2, 3, 4, 5, 6, 7, 8]);
+    let args = alkomp::ParamsBuilder::new()
+        .param(Some(&data_gpu))
+        .build(Some(0));
+
+    let compute = device.compile_wgsl("main", code, &args.0).unwrap();
+    assert_eq!(compute.local_size(), Some((4, 1, 1)));
+    device.call(&compute, (2, 1, 1), &args.1).unwrap();
+
+    let result = device.get_blocking(&data_gpu).unwrap();
+    assert_eq!(&result[0..8], &[3, 6, 9, 12, 15, 18, 21, 24]);
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn image_round_trips_through_a_texture() {
+    let mut device = alkomp::Device::new(0).unwrap();
+    // 70 texels per row, so the readback rows need padding to 256 bytes
+    let image = image::RgbaImage::from_fn(70, 3, |x, y| {
+        image::Rgba([x as u8, y as u8, (x + y) as u8, 255])
+    });
+
+    let texture = device.image_to_texture(&image);
+    assert_eq!((texture.width, texture.height), (70, 3));
+
+    let read = futures::executor::block_on(device.texture_to_image(&texture)).unwrap();
+    assert_eq!(read, image);
+}
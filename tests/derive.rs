@@ -0,0 +1,38 @@
+#![cfg(feature = "derive")]
+use alkomp::GpuBuffer;
+
+#[derive(GpuBuffer, Debug, PartialEq)]
+struct Particles {
+    pos: Vec<f32>,
+    #[gpu(binding = 3)]
+    vel: Vec<f32>,
+    ids: Vec<u32>,
+}
+
+#[test]
+fn derived_fields_round_trip() {
+    let particles = Particles {
+        pos: vec![0.0, 1.0, 2.0],
+        vel: vec![0.5, -0.5, 0.25],
+        ids: vec![7, 8, 9],
+    };
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let gpu = particles.to_device(&mut device);
+    assert_eq!(gpu.ids.len(), 3);
+
+    let (layout, entries) = Particles::params(&gpu).build(Some(0));
+    let mut bindings: Vec<u32> = layout.set_bind_group_layouts[&0].keys().cloned().collect();
+    bindings.sort();
+    assert_eq!(bindings, vec![0, 2, 3]);
+    assert_eq!(entries.len(), 3);
+
+    let read = Particles::get(&mut device, &gpu).unwrap();
+    assert_eq!(read, particles);
+}
+
+#[test]
+fn duplicate_bindings_are_rejected() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/duplicate_binding.rs");
+}
@@ -1,27 +1,23 @@
-use alkomp;
 use ndarray as nd;
 
 #[test]
 fn ndarray_to_device() {
-    let mut device = alkomp::Device::new(0);
+    let mut device = alkomp::Device::new(0).unwrap();
 
     let arr: nd::Array<f32, _> = nd::Array::ones((5, 2, 2));
 
     let size_gpu = device.to_device(arr.shape());
-    let data_gpu = device.to_device(&arr.as_slice().unwrap());
+    let data_gpu = device.to_device(arr.as_slice().unwrap());
 
-    let shape = futures::executor::block_on(device.get(&size_gpu)).unwrap();
-    let data = futures::executor::block_on(device.get(&data_gpu)).unwrap();
+    let shape = device.get_blocking(&size_gpu).unwrap();
+    let data = device.get_blocking(&data_gpu).unwrap();
 
-    let shape = &shape[..];
-    let data = &data[0..data.len() - 1];
-
-    let x = nd::ArrayView::from_shape(shape, data).unwrap();
+    let x = nd::ArrayView::from_shape(&shape[..], &data[..]).unwrap();
 
     assert!(x == arr.into_dyn());
 }
 
-#[cfg(feature = "shaderc")]
+#[cfg(feature = "glsl")]
 #[test]
 fn ndarray_compute_device() {
     let code = "
@@ -44,14 +40,14 @@ fn ndarray_compute_device() {
     let mut spirv = alkomp::glslhelper::GLSLCompile::new(&code);
     let shader = spirv.compile("main").unwrap();
 
-    let mut device = alkomp::Device::new(0);
+    let mut device = alkomp::Device::new(0).unwrap();
 
     let arr: nd::Array<f32, _> = nd::Array::ones((2, 3));
 
     let s: Vec<u32> = arr.shape().iter().map(|x| *x as u32).collect();
 
     let size_gpu = device.to_device(s.as_slice());
-    let data_gpu = device.to_device(&arr.as_slice().unwrap());
+    let data_gpu = device.to_device(arr.as_slice().unwrap());
 
     let args = alkomp::ParamsBuilder::new()
         .param(Some(&data_gpu))
@@ -60,16 +56,32 @@ fn ndarray_compute_device() {
 
     let compute = device.compile("main", &shader, &args.0).unwrap();
 
-    device.call(compute, (1, 1, 1), &args.1);
+    device.call(&compute, (1, 1, 1), &args.1).unwrap();
 
-    let shape = futures::executor::block_on(device.get(&size_gpu)).unwrap();
-    let data = futures::executor::block_on(device.get(&data_gpu)).unwrap();
+    let shape = device.get_blocking(&size_gpu).unwrap();
+    let data = device.get_blocking(&data_gpu).unwrap();
 
-    let _shape = &shape[0..shape.len() - 1];
-    let data = &data[0..data.len() - 1];
+    assert_eq!(&[2, 3], &shape[..]);
 
-    let x = nd::ArrayView::from_shape(arr.shape(), data).unwrap();
+    let x = nd::ArrayView::from_shape(arr.shape(), &data[..]).unwrap();
 
     let expected = nd::array![[2.0, 3.0, 1.0], [1.0, 1.0, 1.0]];
     assert!(x == expected.into_dyn());
 }
+
+#[cfg(feature = "ndarray")]
+#[test]
+fn array_round_trip_keeps_shape() {
+    let mut device = alkomp::Device::new(0).unwrap();
+
+    let arr: nd::Array<f32, _> = nd::Array::from_shape_fn((3, 4), |(i, j)| (i * 4 + j) as f32);
+    // Transposed views are uploaded in standard layout
+    let transposed = arr.t();
+    let gpu = device.to_device_array(&transposed);
+
+    let shape = device.get_blocking(&gpu.shape).unwrap();
+    assert_eq!(&[4, 3], &shape[..]);
+
+    let back = futures::executor::block_on(device.get_array(&gpu)).unwrap();
+    assert_eq!(back, transposed);
+}
@@ -0,0 +1,167 @@
+#![cfg(feature = "glsl")]
+use std::collections::HashMap;
+
+#[test]
+fn reduce_by_key_matches_cpu() {
+    let keys: Vec<u32> = vec![3, 1, 3, 2, 1, 3, 7, 2, 9, 3, 1];
+    let values: Vec<f32> = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11.0];
+
+    let mut expected: HashMap<u32, f32> = HashMap::new();
+    for (k, v) in keys.iter().zip(values.iter()) {
+        *expected.entry(*k).or_insert(0.0) += v;
+    }
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let keys_gpu = device.to_device(keys.as_slice());
+    let values_gpu = device.to_device(values.as_slice());
+
+    let (out_keys, out_values) = device
+        .reduce_by_key(&keys_gpu, &values_gpu, alkomp::ReduceOp::Sum)
+        .unwrap();
+
+    let out_keys = device.get_blocking(&out_keys).unwrap();
+    let out_values = device.get_blocking(&out_values).unwrap();
+
+    let mut expected_keys: Vec<u32> = expected.keys().cloned().collect();
+    expected_keys.sort();
+    assert_eq!(&expected_keys[..], &out_keys[..]);
+    for (k, v) in out_keys.iter().zip(out_values.iter()) {
+        assert_eq!(expected[k], *v);
+    }
+
+    let short_gpu = device.to_device(&values[..5]);
+    match device.reduce_by_key(&keys_gpu, &short_gpu, alkomp::ReduceOp::Sum) {
+        Err(alkomp::CallError::LengthMismatch { expected, found }) => {
+            assert_eq!((expected, found), (11, 5))
+        }
+        _ => panic!("expected a length mismatch"),
+    }
+}
+
+#[test]
+fn reduce_by_key_of_one_long_run() {
+    let n = 100_000;
+    let keys: Vec<u32> = vec![5; n];
+    let values: Vec<f32> = (0..n).map(|i| (i % 4) as f32).collect();
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let keys_gpu = device.to_device(keys.as_slice());
+    let values_gpu = device.to_device(values.as_slice());
+
+    let (out_keys, out_values) = device
+        .reduce_by_key(&keys_gpu, &values_gpu, alkomp::ReduceOp::Max)
+        .unwrap();
+    assert_eq!(&device.get_blocking(&out_keys).unwrap()[..], &[5]);
+    assert_eq!(&device.get_blocking(&out_values).unwrap()[..], &[3.0]);
+}
+
+#[test]
+fn reduce_of_empty_buffer_is_the_identity() {
+    let mut device = alkomp::Device::new(0).unwrap();
+    let empty = device.alloc::<i32>(0);
+    let min = device.reduce(&empty, alkomp::ReduceOp::Min, None).unwrap();
+    assert_eq!(min, i32::MAX);
+    let sum = device.reduce(&empty, alkomp::ReduceOp::Sum, None).unwrap();
+    assert_eq!(sum, 0);
+}
+
+#[test]
+fn reduce_reports_each_pass() {
+    let arr: Vec<u32> = (1..=1000).collect();
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let arr_gpu = device.to_device(arr.as_slice());
+
+    let mut passes = vec![];
+    let mut progress = |pass: usize, partial: &[u32]| passes.push((pass, partial.len()));
+    let sum = device
+        .reduce(&arr_gpu, alkomp::ReduceOp::Sum, Some(&mut progress))
+        .unwrap();
+
+    assert_eq!(500500, sum);
+    assert_eq!(vec![(1, 4), (2, 1)], passes);
+}
+
+#[test]
+fn scan_exclusive_and_inclusive() {
+    let mut device = alkomp::Device::new(0).unwrap();
+    let small = device.to_device(&[1u32, 2, 3, 4]);
+    let exclusive = device.scan(&small, false).unwrap();
+    assert_eq!(&device.get_blocking(&exclusive).unwrap()[..], &[0, 1, 3, 6]);
+    let inclusive = device.scan(&small, true).unwrap();
+    assert_eq!(
+        &device.get_blocking(&inclusive).unwrap()[..],
+        &[1, 3, 6, 10]
+    );
+
+    // Three levels of block sums
+    let arr: Vec<u32> = (0..300_000).map(|i| i % 5).collect();
+    let arr_gpu = device.to_device(arr.as_slice());
+    let scanned = device.scan(&arr_gpu, true).unwrap();
+    let expected: Vec<u32> = arr
+        .iter()
+        .scan(0, |acc, x| {
+            *acc += x;
+            Some(*acc)
+        })
+        .collect();
+    assert_eq!(&device.get_blocking(&scanned).unwrap()[..], &expected[..]);
+
+    let ints = device.to_device(&[3i32, -5, 2, 0, -1]);
+    let scanned = device.scan(&ints, false).unwrap();
+    assert_eq!(
+        &device.get_blocking(&scanned).unwrap()[..],
+        &[0, 3, -2, 0, 0]
+    );
+}
+
+#[test]
+fn buffers_equal_on_device() {
+    let a: Vec<f32> = (0..1000).map(|x| x as f32).collect();
+    let mut b = a.clone();
+
+    let mut device = alkomp::Device::new(0).unwrap();
+    let a_gpu = device.to_device(a.as_slice());
+    let b_gpu = device.to_device(b.as_slice());
+    assert!(device.buffers_equal(&a_gpu, &b_gpu));
+
+    b[777] = -1.0;
+    let b_gpu = device.to_device(b.as_slice());
+    assert!(!device.buffers_equal(&a_gpu, &b_gpu));
+
+    let short_gpu = device.to_device(&a[..999]);
+    assert!(!device.buffers_equal(&a_gpu, &short_gpu));
+}
+
+#[test]
+fn random_is_reproducible() {
+    let mut device = alkomp::Device::new(0).unwrap();
+
+    let a = device.random(10000, 42);
+    let b = device.random(10000, 42);
+    let c = device.random(10000, 43);
+
+    let a = device.get_blocking(&a).unwrap();
+    let b = device.get_blocking(&b).unwrap();
+    let c = device.get_blocking(&c).unwrap();
+    let (a, b, c) = (&a[0..10000], &b[0..10000], &c[0..10000]);
+
+    assert_eq!(a, b);
+    assert_ne!(a, c);
+    assert!(a.iter().all(|x| *x >= 0.0 && *x < 1.0));
+
+    let mean = a.iter().sum::<f32>() / a.len() as f32;
+    assert!((mean - 0.5).abs() < 0.02);
+}
+
+#[test]
+fn iota_counts_up() {
+    let mut device = alkomp::Device::new(0).unwrap();
+
+    let indices = device.iota(1000);
+    assert_eq!(indices.size, 4000);
+
+    let indices = device.get_blocking(&indices).unwrap();
+    let expected: Vec<u32> = (0..1000).collect();
+    assert_eq!(&indices[0..1000], expected.as_slice());
+}
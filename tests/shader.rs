@@ -0,0 +1,72 @@
+fn write_temp(name: &str, bytes: &[u8]) -> String {
+    let path = std::env::temp_dir().join(name);
+    std::fs::write(&path, bytes).unwrap();
+    path.to_str().unwrap().to_string()
+}
+
+#[test]
+fn load_shader_errors() {
+    match alkomp::load_shader("does/not/exist.spv") {
+        Err(alkomp::ShaderLoadError::Io(_)) => {}
+        other => panic!("expected an io error, got {:?}", other),
+    }
+
+    let glsl = write_temp(
+        "alkomp_not_spirv.comp",
+        b"#version 450\n\nvoid main() {}\n\n\n",
+    );
+    match alkomp::load_shader(&glsl) {
+        Err(alkomp::ShaderLoadError::NotSpirv { found_magic }) => {
+            assert_eq!(found_magic, u32::from_le_bytes(*b"#ver"))
+        }
+        other => panic!("expected a magic number error, got {:?}", other),
+    }
+
+    let truncated = write_temp("alkomp_truncated.spv", &[0x03, 0x02, 0x23, 0x07, 0x00]);
+    match alkomp::load_shader(&truncated) {
+        Err(alkomp::ShaderLoadError::UnalignedLength) => {}
+        other => panic!("expected a length error, got {:?}", other),
+    }
+
+    let header = write_temp(
+        "alkomp_header.spv",
+        &[0x03, 0x02, 0x23, 0x07, 0x00, 0x00, 0x01, 0x00],
+    );
+    assert_eq!(
+        alkomp::load_shader(&header).unwrap(),
+        vec![0x0723_0203, 0x0001_0000]
+    );
+}
+
+#[cfg(feature = "glsl")]
+#[test]
+fn compile_output_collects_warnings() {
+    let clean = "#version 450\nlayout(local_size_x = 1) in;\nvoid main() {}\n";
+    let output = alkomp::GLSLCompile::new(clean)
+        .compile_with_diagnostics("main")
+        .unwrap();
+    assert!(!output.spirv.is_empty());
+    assert!(output.warnings.is_empty());
+
+    let noisy = "#version 450\n#extension GL_EXT_not_an_extension : warn\nlayout(local_size_x = 1) in;\nvoid main() {}\n";
+    let output = alkomp::GLSLCompile::new(noisy)
+        .compile_with_diagnostics("main")
+        .unwrap();
+    assert!(!output.spirv.is_empty());
+    // The summary line shaderc appends is not a warning of its own
+    assert_eq!(output.warnings.len(), 1);
+    let warning = &output.warnings[0];
+    assert_eq!(warning.line, Some(2));
+    assert!(warning.message.contains("GL_EXT_not_an_extension"));
+}
+
+#[cfg(feature = "rspirv")]
+#[test]
+fn disassemble_rejects_invalid_modules() {
+    let header = [0x0723_0203, 0x0001_0000, 0, 1, 0];
+    let text = alkomp::spirv::disassemble(&header).unwrap();
+    assert!(text.contains("SPIR-V"));
+
+    assert!(alkomp::spirv::disassemble(&header[..2]).is_err());
+    assert!(alkomp::spirv::disassemble(&[0xdead_beef]).is_err());
+}
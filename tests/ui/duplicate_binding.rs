@@ -0,0 +1,10 @@
+#![allow(dead_code)]
+
+#[derive(alkomp::GpuBuffer)]
+struct Particles {
+    pos: Vec<f32>,
+    #[gpu(binding = 0)]
+    vel: Vec<f32>,
+}
+
+fn main() {}
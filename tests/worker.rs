@@ -0,0 +1,39 @@
+#[test]
+fn jobs_from_many_threads() {
+    let worker = alkomp::Device::new(0).unwrap().spawn_worker();
+
+    let threads: Vec<_> = (0..4u32)
+        .map(|t| {
+            let worker = worker.clone();
+            std::thread::spawn(move || {
+                let input: Vec<u32> = vec![t; 16];
+                let expected = input.clone();
+                let result = worker.run(move |device| {
+                    let data_gpu = device.to_device(input.as_slice());
+                    device.get_blocking(&data_gpu).unwrap()
+                });
+                let output = futures::executor::block_on(result).unwrap();
+                assert_eq!(&output[0..16], expected.as_slice());
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+}
+
+#[test]
+fn worker_survives_a_panicking_job() {
+    let worker = alkomp::Device::new(0).unwrap().spawn_worker();
+
+    let failed = worker.run(|_| -> u32 { panic!("job failed") });
+    assert!(futures::executor::block_on(failed).is_err());
+
+    let result = worker.run(|device| {
+        let data_gpu = device.to_device(&[1u32, 2, 3]);
+        device.get_blocking(&data_gpu).unwrap()
+    });
+    let output = futures::executor::block_on(result).unwrap();
+    assert_eq!(&output[0..3], &[1, 2, 3]);
+}